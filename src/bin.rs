@@ -1,5 +1,9 @@
 mod modules;
-use modules::{config, globals, leases, scopes};
+use modules::{
+    audit, config, conflicts, drift, explain, fingerprint, globals, hosts, lease_time, leases,
+    lint, mqtt, overlaps, plan, render, reqwest_handler, scopes, stats, watcher, webhooks,
+};
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 use crate::modules::error::DhcpctlError;
@@ -12,6 +16,31 @@ const CONFIG_NAME: &str = "config";
     author = "Author Patrick Kerwood <patrick@kerwood.dk>"
 )]
 struct Dhcpctl {
+    #[structopt(
+        long,
+        global = true,
+        env = "DHCPCTL_PROFILE",
+        help = "Use the API URL and token from this profile."
+    )]
+    profile: Option<String>,
+
+    #[structopt(
+        long,
+        global = true,
+        env = "DHCPCTL_TZ",
+        default_value = "local",
+        help = "Show times in this time zone: local, utc or an IANA name like Europe/Copenhagen."
+    )]
+    tz: lease_time::DisplayZone,
+
+    #[structopt(
+        long,
+        global = true,
+        takes_value = false,
+        help = "Show times relative to now, eg. 'in 2h13m' or '3d0h ago'."
+    )]
+    relative: bool,
+
     #[structopt(subcommand)]
     cmd: Cmd,
 }
@@ -29,6 +58,300 @@ enum Cmd {
 
     #[structopt(name = "leases", about = "Get all DHCP leases.")]
     Leases(LeaseType),
+
+    #[structopt(name = "hosts", about = "Get host reservations.")]
+    Hosts(HostType),
+
+    #[structopt(
+        name = "stats",
+        about = "Summarize the leases or export scope and lease metrics."
+    )]
+    Stats {
+        #[structopt(
+            long,
+            short,
+            default_value = "table",
+            possible_values = &["table", "json", "influx", "prom-textfile"],
+            help = "Output format."
+        )]
+        format: stats::StatsFormat,
+
+        #[structopt(
+            long,
+            short,
+            help = "Write the output atomically to a file instead of stdout."
+        )]
+        write: Option<PathBuf>,
+
+        #[structopt(
+            long,
+            short,
+            default_value = "10",
+            help = "Rows per summary table, the rest are grouped as (other)."
+        )]
+        top: usize,
+
+        #[structopt(
+            long,
+            short,
+            takes_value = false,
+            help = "Look up the vendor on MAC addresses for the OUI summary."
+        )]
+        mac_lookup: bool,
+    },
+
+    #[structopt(name = "webhook", about = "Manage lease event webhooks.")]
+    Webhook(WebhookType),
+
+    #[structopt(
+        name = "daemon",
+        about = "Watch leases and send events to the configured webhooks."
+    )]
+    Daemon {
+        #[structopt(
+            long,
+            short,
+            default_value = "60",
+            help = "Seconds between polling the leases."
+        )]
+        interval: u64,
+
+        #[structopt(
+            long,
+            short,
+            default_value = "3",
+            help = "Delivery retries before an event goes to the dead-letter file."
+        )]
+        retries: u32,
+
+        #[structopt(
+            long,
+            short,
+            takes_value = false,
+            help = "Look up the vendor on new MAC addresses and send unknown-vendor events."
+        )]
+        mac_lookup: bool,
+
+        #[structopt(long, takes_value = false, help = "Poll once and exit.")]
+        once: bool,
+    },
+
+    #[structopt(name = "mqtt", about = "Publish lease state to an MQTT broker.")]
+    Mqtt(MqttType),
+
+    #[structopt(
+        name = "explain",
+        about = "Show everything known about an IP, MAC address or hostname."
+    )]
+    Explain {
+        #[structopt(help = "IP, MAC address or hostname.")]
+        target: String,
+
+        #[structopt(
+            long,
+            short,
+            takes_value = false,
+            help = "Look up the vendor on MAC addresses."
+        )]
+        mac_lookup: bool,
+    },
+
+    #[structopt(
+        name = "plan",
+        about = "Show the changes needed to make the live scopes and hosts match a YAML file."
+    )]
+    Plan {
+        #[structopt(long, short, help = "YAML file with 'scopes' and/or 'hosts'.")]
+        file: PathBuf,
+    },
+
+    #[structopt(
+        name = "apply",
+        about = "Push the changes from 'dhcpctl plan' to the API."
+    )]
+    Apply {
+        #[structopt(long, short, help = "YAML file with 'scopes' and/or 'hosts'.")]
+        file: PathBuf,
+
+        #[structopt(long, short, takes_value = false, help = "Don't ask for confirmation.")]
+        yes: bool,
+    },
+
+    #[structopt(
+        name = "render",
+        about = "Render a dhcpd.conf from a YAML or TOML network definition."
+    )]
+    Render {
+        #[structopt(
+            long,
+            short,
+            help = "YAML or TOML file with 'globals', 'scopes' and 'hosts'."
+        )]
+        file: PathBuf,
+
+        #[structopt(
+            long,
+            default_value = "error",
+            possible_values = &["info", "warning", "error"],
+            help = "Don't render when a problem of this severity or higher is found."
+        )]
+        fail_on: lint::Severity,
+    },
+
+    #[structopt(
+        name = "audit",
+        about = "Show the log of changes made through dhcpctl."
+    )]
+    Audit(AuditType),
+
+    #[structopt(
+        name = "drift",
+        about = "Compare the live scopes and globals with a snapshot, a config or another server."
+    )]
+    Drift {
+        #[structopt(
+            long,
+            required_unless_one = &["against-profile", "save"],
+            conflicts_with = "against-profile",
+            help = "Snapshot (.json), network definition (.yaml or .toml) or dhcpd.conf."
+        )]
+        against: Option<PathBuf>,
+
+        #[structopt(long, help = "Compare with the server of this profile.")]
+        against_profile: Option<String>,
+
+        #[structopt(
+            long,
+            conflicts_with_all = &["against", "against-profile"],
+            help = "Save the live scopes and globals as a snapshot instead."
+        )]
+        save: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "raw",
+        about = "Print the API response for a path as-is, for debugging."
+    )]
+    Raw {
+        #[structopt(help = "API path, eg. /leases/ or /config/scopes")]
+        path: String,
+    },
+
+    #[structopt(
+        name = "lint",
+        about = "Check the scopes and globals for misconfigurations."
+    )]
+    Lint {
+        #[structopt(
+            long,
+            default_value = "error",
+            possible_values = &["info", "warning", "error"],
+            help = "Exit non-zero when a problem of this severity or higher is found."
+        )]
+        fail_on: lint::Severity,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum AuditType {
+    #[structopt(about = "List the changes sent to the API.")]
+    List {
+        #[structopt(long, short, help = "Only show the last N entries.")]
+        last: Option<usize>,
+
+        #[structopt(long, short, takes_value = false, help = "Include the request bodies.")]
+        body: bool,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum MqttType {
+    #[structopt(
+        about = "Publish leases as retained messages under <prefix>/<server>/leases/<mac> and presence on change."
+    )]
+    Publish {
+        #[structopt(long, short, help = "MQTT broker, eg. tcp://localhost:1883")]
+        broker: String,
+
+        #[structopt(
+            long,
+            short,
+            help = "Server name used in the topics. Defaults to the API host."
+        )]
+        server: Option<String>,
+
+        #[structopt(long, default_value = "dhcp", help = "Topic prefix.")]
+        prefix: String,
+
+        #[structopt(long, short, help = "MQTT username.")]
+        username: Option<String>,
+
+        #[structopt(long, short, help = "MQTT password.")]
+        password: Option<String>,
+
+        #[structopt(
+            long,
+            takes_value = false,
+            help = "Publish Home Assistant device_tracker discovery payloads."
+        )]
+        homeassistant: bool,
+
+        #[structopt(
+            long,
+            short,
+            default_value = "60",
+            help = "Seconds between polling the leases."
+        )]
+        interval: u64,
+
+        #[structopt(long, takes_value = false, help = "Publish once and exit.")]
+        once: bool,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum WebhookType {
+    #[structopt(about = "Add or replace a webhook.")]
+    Add {
+        #[structopt(help = "The URL to POST events to.")]
+        url: String,
+
+        #[structopt(
+            long,
+            short,
+            default_value = "generic",
+            possible_values = &["generic", "slack", "teams"],
+            help = "Payload template."
+        )]
+        template: webhooks::WebhookTemplate,
+
+        #[structopt(
+            long,
+            short,
+            help = "Message template, eg. '[{event}] {mac} {ip} {hostname} {previous}'."
+        )]
+        message: Option<String>,
+
+        #[structopt(
+            long = "event",
+            short,
+            possible_values = &["new-mac", "ip-change", "hostname-change", "lease-expired", "unknown-vendor"],
+            help = "Only send these events. Can be repeated, defaults to all events."
+        )]
+        events: Vec<watcher::EventKind>,
+    },
+    #[structopt(about = "Remove a webhook.")]
+    Remove {
+        #[structopt(help = "The URL of the webhook.")]
+        url: String,
+    },
+    #[structopt(about = "List webhooks.")]
+    List {},
+    #[structopt(about = "Send a test event to one or all webhooks.")]
+    Test {
+        #[structopt(help = "Webhook URL, does not have to be configured.")]
+        url: Option<String>,
+    },
 }
 
 #[derive(StructOpt, Debug)]
@@ -40,12 +363,141 @@ enum ScopeType {
 
         #[structopt(long, short, takes_value = false, help = "Include DNS servers.")]
         dns: bool,
+
+        #[structopt(
+            long,
+            short,
+            possible_values = &["shared-network"],
+            help = "Group the subnets and sum their usage."
+        )]
+        group_by: Option<scopes::GroupBy>,
     },
-    #[structopt(about = "Get a specific scope.")]
+    #[structopt(about = "Get the scopes matching a network ID, CIDR or host IP.")]
     Get {
-        #[structopt(help = "The string to search for.")]
+        #[structopt(help = "Network ID, CIDR or IP, Eg. 10.3.0.0, 10.3.0.0/16 or 10.3.0.120")]
         subnet_id: String,
     },
+    #[structopt(about = "List unused addresses in a subnet for static assignments.")]
+    Free {
+        #[structopt(help = "Network ID, CIDR or IP of the subnet.")]
+        subnet_id: String,
+
+        #[structopt(
+            long,
+            short,
+            help = "Print this many single addresses instead of free blocks."
+        )]
+        count: Option<usize>,
+
+        #[structopt(
+            long,
+            short,
+            takes_value = false,
+            help = "Exclude the dynamic range. Always done, since 'hosts add' refuses addresses in it."
+        )]
+        #[allow(dead_code)]
+        outside_range: bool,
+    },
+    #[structopt(
+        about = "Find overlapping and duplicate scopes across every profile and scope file."
+    )]
+    Overlaps {
+        #[structopt(
+            long = "file",
+            short,
+            help = "JSON file with scopes in the /config/scopes format. Can be repeated."
+        )]
+        files: Vec<PathBuf>,
+
+        #[structopt(
+            long,
+            default_value = "error",
+            possible_values = &["info", "warning", "error"],
+            help = "Exit non-zero when a problem of this severity or higher is found."
+        )]
+        fail_on: lint::Severity,
+    },
+    #[structopt(about = "Find the scope containing each IP.")]
+    Which {
+        #[structopt(required = true, help = "One or more IPs.")]
+        ips: Vec<String>,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum HostType {
+    #[structopt(about = "List all host reservations.")]
+    List {
+        #[structopt(help = "Only hosts with a fixed address in this CIDR, Eg. 10.3.0.0/24")]
+        cidr: Option<String>,
+
+        #[structopt(
+            long,
+            short,
+            help = "Read the hosts from a dhcpd.conf instead of the API."
+        )]
+        file: Option<PathBuf>,
+    },
+    #[structopt(about = "Show a host reservation by name, MAC address or fixed address.")]
+    Get {
+        #[structopt(help = "Name, MAC address or fixed address.")]
+        target: String,
+
+        #[structopt(
+            long,
+            short,
+            help = "Read the hosts from a dhcpd.conf instead of the API."
+        )]
+        file: Option<PathBuf>,
+    },
+    #[structopt(about = "Add a host reservation outside the dynamic ranges.")]
+    Add {
+        #[structopt(long, short, help = "Host name, Eg. printer-3")]
+        name: String,
+
+        #[structopt(long, short, help = "MAC address, Eg. 00:1a:2b:3c:4d:5e")]
+        mac: String,
+
+        #[structopt(long, short, help = "Fixed address, Eg. 10.3.0.20")]
+        ip: String,
+
+        #[structopt(long, short, takes_value = false, help = "Don't ask for confirmation.")]
+        yes: bool,
+
+        #[structopt(
+            long,
+            takes_value = false,
+            help = "Validate and show the host declaration without adding it."
+        )]
+        dry_run: bool,
+    },
+    #[structopt(about = "Remove a host reservation by name, MAC address or fixed address.")]
+    Remove {
+        #[structopt(help = "Name, MAC address or fixed address.")]
+        target: String,
+
+        #[structopt(long, short, takes_value = false, help = "Don't ask for confirmation.")]
+        yes: bool,
+
+        #[structopt(
+            long,
+            takes_value = false,
+            help = "Show the host declaration without removing it."
+        )]
+        dry_run: bool,
+    },
+    #[structopt(about = "Search for hosts by name, MAC address or fixed address.")]
+    Search {
+        #[structopt(help = "The string to search for.")]
+        string: String,
+
+        #[structopt(
+            long,
+            short,
+            help = "Read the hosts from a dhcpd.conf instead of the API."
+        )]
+        file: Option<PathBuf>,
+    },
 }
 
 #[derive(StructOpt, Debug)]
@@ -62,6 +514,43 @@ enum LeaseType {
             help = "Look up the vendor on MAC addresses."
         )]
         mac_lookup: bool,
+
+        #[structopt(
+            long,
+            short = "i",
+            takes_value = false,
+            help = "Add a column with the decoded client identifier."
+        )]
+        client_id: bool,
+
+        #[structopt(
+            long,
+            short,
+            takes_value = false,
+            help = "Add a Device column with the OS, family and PXE architecture."
+        )]
+        fingerprint: bool,
+
+        #[structopt(
+            long,
+            short,
+            help = "Only show leases whose device contains this text, eg. android or 'x64 uefi'."
+        )]
+        device: Option<String>,
+
+        #[structopt(
+            long,
+            short,
+            default_value = "table",
+            possible_values = &["table", "json"],
+            help = "Output format."
+        )]
+        output: leases::OutputFormat,
+    },
+    #[structopt(about = "Show the full lease record for an IP or MAC address.")]
+    Get {
+        #[structopt(help = "IP or MAC address, Eg. 10.3.0.120 or 00:1a:2b:3c:4d:5e")]
+        target: String,
     },
     #[structopt(
         about = "Search for leases in the 'client-hostname', 'hardware-ethernet' and 'set-vendor-class-identifier' properties."
@@ -77,6 +566,79 @@ enum LeaseType {
             help = "Look up the vendor on MAC addresses."
         )]
         mac_lookup: bool,
+
+        #[structopt(
+            long,
+            short = "i",
+            takes_value = false,
+            help = "Add a column with the decoded client identifier."
+        )]
+        client_id: bool,
+
+        #[structopt(
+            long,
+            short,
+            takes_value = false,
+            help = "Add a Device column with the OS, family and PXE architecture."
+        )]
+        fingerprint: bool,
+
+        #[structopt(
+            long,
+            short,
+            help = "Only show leases whose device contains this text, eg. android or 'x64 uefi'."
+        )]
+        device: Option<String>,
+
+        #[structopt(
+            long,
+            short,
+            default_value = "table",
+            possible_values = &["table", "json"],
+            help = "Output format."
+        )]
+        output: leases::OutputFormat,
+    },
+    #[structopt(about = "Release the active leases for an IP or MAC address.")]
+    Release {
+        #[structopt(help = "IP or MAC address, Eg. 10.3.0.120 or 00:1a:2b:3c:4d:5e")]
+        target: String,
+
+        #[structopt(long, short, takes_value = false, help = "Don't ask for confirmation.")]
+        yes: bool,
+
+        #[structopt(
+            long,
+            takes_value = false,
+            help = "Show the leases that would be released without releasing them."
+        )]
+        dry_run: bool,
+    },
+    #[structopt(about = "Count active leases grouped by device fingerprint.")]
+    Devices {
+        #[structopt(help = "Specific CIDR, Eg. 10.3.0.0/24")]
+        cidr: Option<String>,
+
+        #[structopt(
+            long,
+            short,
+            default_value = "table",
+            possible_values = &["table", "json"],
+            help = "Output format."
+        )]
+        output: leases::OutputFormat,
+    },
+    #[structopt(
+        about = "Find MACs with several active IPs, hostnames on several MACs and IPs in several active leases."
+    )]
+    Conflicts {
+        #[structopt(
+            long,
+            short,
+            takes_value = false,
+            help = "Combine the leases from every profile."
+        )]
+        all_profiles: bool,
     },
 }
 
@@ -94,20 +656,44 @@ enum Config {
             help = "API authentication token. This will be added as a header eg. 'authorization: xxxxx'."
         )]
         token: Option<String>,
+
+        #[structopt(
+            short,
+            long,
+            help = "strftime format used for times, eg. '%d/%m/%Y %H:%M'."
+        )]
+        date_format: Option<String>,
+
+        #[structopt(long, help = "Refuse changes through this profile, true or false.")]
+        read_only: Option<bool>,
     },
 
     #[structopt(about = "List configuration.")]
     List {},
+
+    #[structopt(about = "Remove a profile.")]
+    RemoveProfile {
+        #[structopt(help = "The profile name.")]
+        name: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
     let args = Dhcpctl::from_args();
+    config::select_profile(args.profile);
+    lease_time::select_display(args.tz, args.relative);
 
     match args.cmd {
         Cmd::Config(config_type) => match config_type {
-            Config::Set { url, token } => {
-                if url.is_none() && token.is_none() {
+            Config::Set {
+                url,
+                token,
+                date_format,
+                read_only,
+            } => {
+                if url.is_none() && token.is_none() && date_format.is_none() && read_only.is_none()
+                {
                     return Err(DhcpctlError::MissingArguments.to_string());
                 }
                 if url.is_some() {
@@ -116,11 +702,21 @@ async fn main() -> Result<(), String> {
                 if token.is_some() {
                     config::set_auth_token(&token.expect("Error unwrapping token option"))?;
                 }
+                if let Some(date_format) = date_format {
+                    config::set_date_format(&date_format)?;
+                }
+                if let Some(read_only) = read_only {
+                    config::set_read_only(read_only)?;
+                }
             }
 
             Config::List {} => {
                 config::print_config()?;
             }
+
+            Config::RemoveProfile { name } => {
+                config::remove_profile(&name)?;
+            }
         },
 
         Cmd::Globals {} => {
@@ -128,23 +724,217 @@ async fn main() -> Result<(), String> {
         }
 
         Cmd::Scopes(scope_type) => match scope_type {
-            ScopeType::List { pxe, dns } => {
-                scopes::list_scopes(pxe, dns).await?;
+            ScopeType::List { pxe, dns, group_by } => {
+                scopes::list_scopes(pxe, dns, group_by).await?;
             }
             ScopeType::Get { subnet_id } => {
                 scopes::get_scope(&subnet_id).await?;
             }
+            // The dynamic range is excluded either way.
+            ScopeType::Free {
+                subnet_id, count, ..
+            } => {
+                scopes::free_addresses(&subnet_id, count).await?;
+            }
+            ScopeType::Overlaps { files, fail_on } => {
+                overlaps::find_overlaps(&files, fail_on).await?;
+            }
+            ScopeType::Which { ips } => {
+                scopes::which_scopes(&ips).await?;
+            }
         },
 
         Cmd::Leases(lease_type) => match lease_type {
-            LeaseType::List { cidr, mac_lookup } => {
+            LeaseType::List {
+                cidr,
+                mac_lookup,
+                client_id,
+                fingerprint,
+                device,
+                output,
+            } => {
                 let cidr = cidr.unwrap_or_default();
-                leases::get_leases(cidr, mac_lookup).await?;
+                let options = leases::ListOptions {
+                    mac_lookup,
+                    client_id,
+                    fingerprint,
+                    device,
+                    output,
+                };
+                leases::get_leases(cidr, &options).await?;
+            }
+            LeaseType::Get { target } => {
+                leases::get_lease(&target).await?;
+            }
+            LeaseType::Search {
+                string,
+                mac_lookup,
+                client_id,
+                fingerprint,
+                device,
+                output,
+            } => {
+                let options = leases::ListOptions {
+                    mac_lookup,
+                    client_id,
+                    fingerprint,
+                    device,
+                    output,
+                };
+                leases::search_leases(string, &options).await?;
+            }
+            LeaseType::Release {
+                target,
+                yes,
+                dry_run,
+            } => {
+                leases::release_lease(&target, yes, dry_run).await?;
+            }
+            LeaseType::Devices { cidr, output } => {
+                fingerprint::device_summary(cidr.unwrap_or_default(), output).await?;
+            }
+            LeaseType::Conflicts { all_profiles } => {
+                conflicts::find_conflicts(all_profiles).await?;
+            }
+        },
+
+        Cmd::Hosts(host_type) => match host_type {
+            HostType::List { cidr, file } => {
+                hosts::list_hosts(cidr, file.as_deref()).await?;
+            }
+            HostType::Get { target, file } => {
+                hosts::get_host(&target, file.as_deref()).await?;
+            }
+            HostType::Search { string, file } => {
+                hosts::search_hosts(&string, file.as_deref()).await?;
+            }
+            HostType::Add {
+                name,
+                mac,
+                ip,
+                yes,
+                dry_run,
+            } => {
+                hosts::add_host(&name, &mac, &ip, yes, dry_run).await?;
+            }
+            HostType::Remove {
+                target,
+                yes,
+                dry_run,
+            } => {
+                hosts::remove_host(&target, yes, dry_run).await?;
+            }
+        },
+
+        Cmd::Stats {
+            format,
+            write,
+            top,
+            mac_lookup,
+        } => {
+            stats::print_stats(stats::StatsOptions {
+                format,
+                write,
+                top,
+                mac_lookup,
+            })
+            .await?;
+        }
+
+        Cmd::Webhook(webhook_type) => match webhook_type {
+            WebhookType::Add {
+                url,
+                template,
+                message,
+                events,
+            } => {
+                webhooks::add_webhook(&url, template, message, events)?;
+            }
+            WebhookType::Remove { url } => {
+                webhooks::remove_webhook(&url)?;
+            }
+            WebhookType::List {} => {
+                webhooks::list_webhooks()?;
             }
-            LeaseType::Search { string, mac_lookup } => {
-                leases::search_leases(string, mac_lookup).await?;
+            WebhookType::Test { url } => {
+                webhooks::test_webhook(url).await?;
             }
         },
+
+        Cmd::Daemon {
+            interval,
+            retries,
+            mac_lookup,
+            once,
+        } => {
+            webhooks::run_daemon(interval, retries, mac_lookup, once).await?;
+        }
+
+        Cmd::Mqtt(mqtt_type) => match mqtt_type {
+            MqttType::Publish {
+                broker,
+                server,
+                prefix,
+                username,
+                password,
+                homeassistant,
+                interval,
+                once,
+            } => {
+                mqtt::publish(mqtt::MqttSettings {
+                    broker,
+                    server,
+                    prefix,
+                    username,
+                    password,
+                    homeassistant,
+                    interval,
+                    once,
+                })
+                .await?;
+            }
+        },
+
+        Cmd::Explain { target, mac_lookup } => {
+            explain::explain(&target, mac_lookup).await?;
+        }
+
+        Cmd::Plan { file } => {
+            plan::plan(&file).await?;
+        }
+
+        Cmd::Apply { file, yes } => {
+            plan::apply(&file, yes).await?;
+        }
+
+        Cmd::Render { file, fail_on } => {
+            render::print_config(&file, fail_on)?;
+        }
+
+        Cmd::Audit(audit_type) => match audit_type {
+            AuditType::List { last, body } => {
+                audit::list_audit(last, body)?;
+            }
+        },
+
+        Cmd::Drift {
+            against,
+            against_profile,
+            save,
+        } => match (save, against, against_profile) {
+            (Some(file), _, _) => drift::save_snapshot(&file).await?,
+            (None, Some(file), _) => drift::drift(drift::Against::File(&file)).await?,
+            (None, None, Some(name)) => drift::drift(drift::Against::Profile(&name)).await?,
+            (None, None, None) => unreachable!("required by structopt"),
+        },
+
+        Cmd::Raw { path } => {
+            reqwest_handler::print_raw(&path).await?;
+        }
+
+        Cmd::Lint { fail_on } => {
+            lint::run_lint(fail_on).await?;
+        }
     }
     Ok(())
 }
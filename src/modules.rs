@@ -1,6 +1,23 @@
+pub mod audit;
 pub mod config;
+pub mod conflicts;
+pub mod dhcpd_conf;
+pub mod drift;
 pub mod error;
+pub mod explain;
+pub mod fingerprint;
 pub mod globals;
+pub mod hosts;
+pub mod lease_time;
 pub mod leases;
+pub mod lint;
+pub mod mqtt;
+pub mod overlaps;
+pub mod plan;
+pub mod render;
 pub mod reqwest_handler;
 pub mod scopes;
+pub mod stats;
+pub mod uid;
+pub mod watcher;
+pub mod webhooks;
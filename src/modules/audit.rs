@@ -0,0 +1,224 @@
+use super::config;
+use super::error::DhcpctlError;
+use super::lease_time::LeaseTime;
+use chrono::prelude::*;
+use prettytable::{cell, format, row, Cell, Row, Table};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+const AUDIT_FILE: &str = "audit.jsonl";
+
+// One line per change sent to the API. The file is only ever appended to.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub user: String,
+    pub profile: String,
+    pub command: String,
+    pub method: String,
+    pub path: String,
+    pub body: Option<Value>,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+fn current_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+pub fn record(
+    method: &str,
+    path: &str,
+    body: Option<Value>,
+    status: Option<u16>,
+    error: Option<String>,
+) -> Result<(), DhcpctlError> {
+    let entry = AuditEntry {
+        timestamp: Utc::now().to_rfc3339(),
+        user: current_user(),
+        profile: config::active_profile_name(),
+        command: env::args().skip(1).collect::<Vec<String>>().join(" "),
+        method: method.to_string(),
+        path: path.to_string(),
+        body,
+        status,
+        error,
+    };
+    append(&config::data_file(AUDIT_FILE)?, &entry)
+}
+
+fn append(file: &Path, entry: &AuditEntry) -> Result<(), DhcpctlError> {
+    let mut file = OpenOptions::new().create(true).append(true).open(file)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+pub fn check_writable() -> Result<(), DhcpctlError> {
+    if config::load_profile()?.read_only {
+        return Err(DhcpctlError::ReadOnlyProfile(config::active_profile_name()));
+    }
+    Ok(())
+}
+
+// Every mutating command prints a summary of the change first. Without
+// --yes the change has to be confirmed, and a closed stdin counts as no.
+pub fn confirm(question: &str, yes: bool) -> Result<bool, DhcpctlError> {
+    check_writable()?;
+    if yes {
+        return Ok(true);
+    }
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+pub fn load_audit() -> Result<Vec<AuditEntry>, DhcpctlError> {
+    read_entries(&config::data_file(AUDIT_FILE)?)
+}
+
+// Lines that don't parse are skipped, so one bad write doesn't hide the rest.
+fn read_entries(file: &Path) -> Result<Vec<AuditEntry>, DhcpctlError> {
+    if !file.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for line in BufReader::new(fs::File::open(file)?).lines() {
+        if let Ok(entry) = serde_json::from_str::<AuditEntry>(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+fn table_format() -> format::TableFormat {
+    format::FormatBuilder::new()
+        .column_separator(' ')
+        .separator(
+            format::LinePosition::Title,
+            format::LineSeparator::new('-', ' ', ' ', ' '),
+        )
+        .padding(1, 1)
+        .build()
+}
+
+pub fn list_audit(last: Option<usize>, with_body: bool) -> Result<(), DhcpctlError> {
+    let entries = load_audit()?;
+    if entries.is_empty() {
+        println!("No audit entries found");
+        return Ok(());
+    }
+    audit_table(&entries, last, with_body)?.printstd();
+    Ok(())
+}
+
+fn audit_table(
+    entries: &[AuditEntry],
+    last: Option<usize>,
+    with_body: bool,
+) -> Result<Table, DhcpctlError> {
+    let skip = last.map(|x| entries.len().saturating_sub(x)).unwrap_or(0);
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(
+        row!(b -> "Time", b -> "User", b -> "Profile", b -> "Command", b -> "Request", b -> "Status"),
+    );
+    for entry in entries.iter().skip(skip) {
+        let mut request = format!("{} {}", entry.method, entry.path);
+        if with_body {
+            if let Some(body) = &entry.body {
+                request = format!("{}\n{}", request, serde_json::to_string_pretty(body)?);
+            }
+        }
+        let status = match (entry.status, &entry.error) {
+            (Some(status), _) if (200..300).contains(&status) => Cell::new(&status.to_string()),
+            (Some(status), _) => Cell::new(&status.to_string()).style_spec("Fr"),
+            (None, Some(error)) => Cell::new(error).style_spec("Fr"),
+            (None, None) => Cell::new(""),
+        };
+        let mut row: Row = row!(
+            LeaseTime::parse(&entry.timestamp),
+            &entry.user,
+            &entry.profile,
+            &entry.command,
+            request,
+        );
+        row.add_cell(status);
+        table.add_row(row);
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(method: &str, path: &str, status: Option<u16>) -> AuditEntry {
+        AuditEntry {
+            timestamp: "2021-05-01T10:00:00+00:00".to_string(),
+            user: "alice".to_string(),
+            profile: "default".to_string(),
+            command: "hosts add printer-1".to_string(),
+            method: method.to_string(),
+            path: path.to_string(),
+            body: Some(json!({"fixed-address": "10.0.1.20"})),
+            status,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn append_and_read_back() {
+        let file = std::env::temp_dir().join(format!("dhcpctl-audit-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&file);
+        assert!(read_entries(&file).unwrap().is_empty());
+
+        append(&file, &entry("POST", "/hosts/", Some(201))).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&file)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+        append(&file, &entry("DELETE", "/hosts/printer-1", Some(404))).unwrap();
+
+        let entries = read_entries(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        let requests: Vec<(&str, &str, Option<u16>)> = entries
+            .iter()
+            .map(|x| (x.method.as_str(), x.path.as_str(), x.status))
+            .collect();
+        assert_eq!(
+            requests,
+            vec![
+                ("POST", "/hosts/", Some(201)),
+                ("DELETE", "/hosts/printer-1", Some(404))
+            ]
+        );
+        assert_eq!(entries[0].body, Some(json!({"fixed-address": "10.0.1.20"})));
+    }
+
+    #[test]
+    fn list_keeps_the_last_entries() {
+        let entries = vec![
+            entry("POST", "/hosts/", Some(201)),
+            entry("PUT", "/hosts/printer-1", Some(200)),
+            entry("DELETE", "/hosts/printer-1", Some(200)),
+        ];
+        assert_eq!(audit_table(&entries, None, false).unwrap().len(), 3);
+        let table = audit_table(&entries, Some(2), true).unwrap();
+        assert_eq!(table.len(), 2);
+        let request = table.get_row(0).unwrap().get_cell(4).unwrap().get_content();
+        assert!(request.starts_with("PUT /hosts/printer-1\n{"));
+        assert!(audit_table(&entries, Some(0), false).unwrap().is_empty());
+    }
+}
@@ -1,13 +1,65 @@
 use super::error::DhcpctlError;
+use super::lease_time;
+use super::webhooks::Webhook;
 use crate::{APP_NAME, CONFIG_NAME};
+use chrono::Utc;
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
+pub const DEFAULT_PROFILE: &str = "default";
+
+static ACTIVE_PROFILE: OnceLock<String> = OnceLock::new();
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Profile {
+    pub api_url: String,
+    pub auth_token: String,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+// The top level URL and token make up the default profile, so config files
+// from before profiles existed keep working.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConfyConfig {
     pub api_url: String,
     pub auth_token: String,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    #[serde(default)]
+    pub date_format: Option<String>,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl ConfyConfig {
+    pub fn profile(&self, name: &str) -> Result<Profile, DhcpctlError> {
+        if name == DEFAULT_PROFILE {
+            return Ok(Profile {
+                api_url: self.api_url.clone(),
+                auth_token: self.auth_token.clone(),
+                read_only: self.read_only,
+            });
+        }
+        self.profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| DhcpctlError::UnknownProfile(name.to_string()))
+    }
+
+    pub fn profile_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        if !self.api_url.is_empty() {
+            names.push(DEFAULT_PROFILE.to_string());
+        }
+        names.extend(self.profiles.keys().cloned());
+        names
+    }
 }
 
 impl Default for ConfyConfig {
@@ -15,6 +67,10 @@ impl Default for ConfyConfig {
         ConfyConfig {
             api_url: "".to_string(),
             auth_token: "".to_string(),
+            profiles: BTreeMap::new(),
+            webhooks: Vec::new(),
+            date_format: None,
+            read_only: false,
         }
     }
 }
@@ -29,10 +85,32 @@ pub fn store_config(conf: &ConfyConfig) -> Result<(), DhcpctlError> {
     Ok(())
 }
 
+pub fn select_profile(name: Option<String>) {
+    if let Some(name) = name {
+        let _ = ACTIVE_PROFILE.set(name);
+    }
+}
+
+pub fn active_profile_name() -> String {
+    ACTIVE_PROFILE
+        .get()
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+pub fn load_profile() -> Result<Profile, DhcpctlError> {
+    load_config()?.profile(&active_profile_name())
+}
+
 pub fn set_auth_token(token: &str) -> Result<(), DhcpctlError> {
     check_if_conf_exists()?;
     let mut conf: ConfyConfig = load_config()?;
-    conf.auth_token = token.to_owned();
+    let name = active_profile_name();
+    if name == DEFAULT_PROFILE {
+        conf.auth_token = token.to_owned();
+    } else {
+        conf.profiles.entry(name).or_default().auth_token = token.to_owned();
+    }
     store_config(&conf)?;
     println!("Token => {}", token.green());
     Ok(())
@@ -41,12 +119,60 @@ pub fn set_auth_token(token: &str) -> Result<(), DhcpctlError> {
 pub fn set_api_url(url: &str) -> Result<(), DhcpctlError> {
     check_if_conf_exists()?;
     let mut conf: ConfyConfig = load_config()?;
-    conf.api_url = url.to_owned();
+    let name = active_profile_name();
+    if name == DEFAULT_PROFILE {
+        conf.api_url = url.to_owned();
+    } else {
+        conf.profiles.entry(name).or_default().api_url = url.to_owned();
+    }
     store_config(&conf)?;
     println!("API => {}", url.green());
     Ok(())
 }
 
+pub fn set_read_only(read_only: bool) -> Result<(), DhcpctlError> {
+    check_if_conf_exists()?;
+    let mut conf: ConfyConfig = load_config()?;
+    let name = active_profile_name();
+    if name == DEFAULT_PROFILE {
+        conf.read_only = read_only;
+    } else {
+        conf.profiles.entry(name).or_default().read_only = read_only;
+    }
+    store_config(&conf)?;
+    println!("Read only => {}", read_only.to_string().green());
+    Ok(())
+}
+
+pub fn set_date_format(format: &str) -> Result<(), DhcpctlError> {
+    lease_time::check_date_format(format)?;
+    check_if_conf_exists()?;
+    let mut conf: ConfyConfig = load_config()?;
+    conf.date_format = Some(format.to_owned());
+    store_config(&conf)?;
+    println!(
+        "Date format => {} ({})",
+        format.green(),
+        Utc::now().format(format)
+    );
+    Ok(())
+}
+
+// The default profile and the one selected for this run can't be removed,
+// since every other command would resolve to a profile that is gone.
+pub fn remove_profile(name: &str) -> Result<(), DhcpctlError> {
+    if name == DEFAULT_PROFILE || name == active_profile_name() {
+        return Err(DhcpctlError::ProfileInUse(name.to_string()));
+    }
+    let mut conf: ConfyConfig = load_config()?;
+    if conf.profiles.remove(name).is_none() {
+        return Err(DhcpctlError::UnknownProfile(name.to_string()));
+    }
+    store_config(&conf)?;
+    println!("Removed profile {}", name.green());
+    Ok(())
+}
+
 pub fn check_if_conf_exists() -> Result<(), DhcpctlError> {
     let file = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)?;
     if !Path::new(&file).exists() {
@@ -56,9 +182,39 @@ pub fn check_if_conf_exists() -> Result<(), DhcpctlError> {
     Ok(())
 }
 
+// State files like the lease snapshot live next to the config file.
+pub fn data_file(file_name: &str) -> Result<PathBuf, DhcpctlError> {
+    let file = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)?;
+    Ok(file.with_file_name(file_name))
+}
+
 pub fn print_config() -> Result<(), DhcpctlError> {
     let conf: ConfyConfig = load_config()?;
     println!("API URL: {}", conf.api_url.green());
     println!("Auth token: {}", conf.auth_token.green());
+    println!(
+        "Date format: {}",
+        conf.date_format
+            .as_deref()
+            .unwrap_or(lease_time::DEFAULT_DATE_FORMAT)
+            .green()
+    );
+    if conf.read_only {
+        println!("Read only: {}", "true".green());
+    }
+    for (name, profile) in conf.profiles.iter() {
+        println!("\n{} {}", "Profile:".bold(), name);
+        println!("API URL: {}", profile.api_url.green());
+        println!("Auth token: {}", profile.auth_token.green());
+        if profile.read_only {
+            println!("Read only: {}", "true".green());
+        }
+    }
+    if !conf.webhooks.is_empty() {
+        println!();
+    }
+    for webhook in conf.webhooks.iter() {
+        println!("Webhook: {} ({})", webhook.url.green(), webhook.template);
+    }
     Ok(())
 }
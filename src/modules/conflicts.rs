@@ -0,0 +1,334 @@
+use super::config;
+use super::config::ConfyConfig;
+use super::error::DhcpctlError;
+use super::lease_time::LeaseTime;
+use super::leases::Lease;
+use super::reqwest_handler;
+use colored::Colorize;
+use prettytable::{cell, format, row, Attr, Cell, Table};
+use std::collections::{BTreeMap, BTreeSet};
+
+struct SourcedLease {
+    source: String,
+    lease: Lease,
+}
+
+struct Conflict<'a> {
+    title: String,
+    leases: Vec<&'a SourcedLease>,
+}
+
+struct Conflicts<'a> {
+    macs: Vec<Conflict<'a>>,
+    hostnames: Vec<Conflict<'a>>,
+    ips: Vec<Conflict<'a>>,
+    shared: Vec<Conflict<'a>>,
+}
+
+fn table_format() -> format::TableFormat {
+    format::FormatBuilder::new()
+        .column_separator(' ')
+        .separator(
+            format::LinePosition::Title,
+            format::LineSeparator::new('-', ' ', ' ', ' '),
+        )
+        .padding(1, 1)
+        .build()
+}
+
+// Groups the active leases by `key` and keeps the groups where the leases
+// disagree on `distinct`, eg. one MAC with several IPs.
+fn group_conflicts<'a>(
+    leases: &[&'a SourcedLease],
+    key: fn(&Lease) -> Option<String>,
+    distinct: fn(&SourcedLease) -> String,
+    title: fn(&str, usize) -> String,
+) -> Vec<Conflict<'a>> {
+    let mut groups: BTreeMap<String, Vec<&SourcedLease>> = BTreeMap::new();
+    for lease in leases.iter() {
+        if let Some(value) = key(&lease.lease) {
+            groups.entry(value).or_default().push(lease);
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter_map(|(value, mut group)| {
+            let count = group
+                .iter()
+                .map(|x| distinct(x))
+                .collect::<BTreeSet<_>>()
+                .len();
+            if count < 2 {
+                return None;
+            }
+            group.sort_by_key(|x| LeaseTime::parse(&x.lease.starts));
+            Some(Conflict {
+                title: title(&value, count),
+                leases: group,
+            })
+        })
+        .collect()
+}
+
+fn print_conflicts(heading: &str, conflicts: &[Conflict], multiple_sources: bool) {
+    println!("\n{} ({})", heading.bold().underline(), conflicts.len());
+    if conflicts.is_empty() {
+        println!("None found");
+    }
+
+    for conflict in conflicts.iter() {
+        println!("\n{}", conflict.title.yellow());
+        let mut table = Table::new();
+        table.set_format(table_format());
+        let mut titles = row!(b -> "MAC Address", b -> "IP", b -> "Hostname", b -> "Starts", b -> "Ends", b -> "Last Transaction");
+        if multiple_sources {
+            titles.insert_cell(0, Cell::new("Profile").with_style(Attr::Bold));
+        }
+        table.set_titles(titles);
+
+        for sourced in conflict.leases.iter() {
+            let lease = &sourced.lease;
+            let mut row = row!(
+                &lease.hardware_ethernet,
+                &lease.ip,
+                lease.client_hostname.clone().unwrap_or_default(),
+                LeaseTime::parse(&lease.starts),
+                LeaseTime::parse(&lease.ends),
+                LeaseTime::parse(&lease.cltt),
+            );
+            if multiple_sources {
+                row.insert_cell(0, Cell::new(&sourced.source));
+            }
+            table.add_row(row);
+        }
+        table.printstd();
+    }
+}
+
+// Profiles that can't be reached are reported and returned by name, so the
+// others can still be checked before the command fails.
+async fn load_leases(all_profiles: bool) -> Result<(Vec<SourcedLease>, Vec<String>), DhcpctlError> {
+    let mut leases = Vec::new();
+    let mut failed = Vec::new();
+    if !all_profiles {
+        let payload: Vec<Lease> = reqwest_handler::run("/leases/").await?;
+        let source = config::active_profile_name();
+        leases.extend(payload.into_iter().map(|lease| SourcedLease {
+            source: source.clone(),
+            lease,
+        }));
+        return Ok((leases, failed));
+    }
+
+    let conf: ConfyConfig = config::load_config()?;
+    for name in conf.profile_names() {
+        let profile = conf.profile(&name)?;
+        match reqwest_handler::run_on::<Vec<Lease>>(&profile, "/leases/").await {
+            Ok(payload) => leases.extend(payload.into_iter().map(|lease| SourcedLease {
+                source: name.clone(),
+                lease,
+            })),
+            Err(error) => {
+                eprintln!("{} {}: {}", "Skipping profile".yellow(), name, error);
+                failed.push(name);
+            }
+        }
+    }
+    Ok((leases, failed))
+}
+
+fn conflicts_in(leases: &[SourcedLease]) -> Conflicts<'_> {
+    let active: Vec<&SourcedLease> = leases
+        .iter()
+        .filter(|x| x.lease.binding_state == "active")
+        .collect();
+
+    let macs = group_conflicts(
+        &active,
+        |x| Some(x.hardware_ethernet.to_lowercase()),
+        |x| x.lease.ip.clone(),
+        |mac, count| format!("MAC {} holds {} active IPs", mac, count),
+    );
+    let hostnames = group_conflicts(
+        &active,
+        |x| {
+            x.client_hostname
+                .as_ref()
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_lowercase())
+        },
+        |x| x.lease.hardware_ethernet.to_lowercase(),
+        |hostname, count| format!("Hostname {} is used by {} MACs", hostname, count),
+    );
+    let ips = group_conflicts(
+        &active,
+        |x| Some(x.ip.clone()),
+        |x| x.lease.hardware_ethernet.to_lowercase(),
+        |ip, count| format!("IP {} is leased to {} MACs", ip, count),
+    );
+    // Failover peers both report the leases they share, so the same lease
+    // on several servers is only shown for information.
+    let shared = group_conflicts(
+        &active,
+        |x| Some(format!("{} {}", x.ip, x.hardware_ethernet.to_lowercase())),
+        |x| x.source.clone(),
+        |lease, count| format!("{} is reported by {} profiles", lease, count),
+    );
+
+    Conflicts {
+        macs,
+        hostnames,
+        ips,
+        shared,
+    }
+}
+
+pub async fn find_conflicts(all_profiles: bool) -> Result<(), DhcpctlError> {
+    let (leases, failed) = load_leases(all_profiles).await?;
+    let multiple_sources = leases
+        .iter()
+        .map(|x| &x.source)
+        .collect::<BTreeSet<_>>()
+        .len()
+        > 1;
+    let conflicts = conflicts_in(&leases);
+
+    print_conflicts(
+        "MACs with several active IPs",
+        &conflicts.macs,
+        multiple_sources,
+    );
+    print_conflicts(
+        "Hostnames on several MACs",
+        &conflicts.hostnames,
+        multiple_sources,
+    );
+    print_conflicts(
+        "IPs leased to several MACs",
+        &conflicts.ips,
+        multiple_sources,
+    );
+    if multiple_sources {
+        print_shared(&conflicts.shared);
+    }
+    if !failed.is_empty() {
+        return Err(DhcpctlError::UnreachableProfiles(failed));
+    }
+    Ok(())
+}
+
+fn print_shared(shared: &[Conflict]) {
+    println!(
+        "\n{} ({})",
+        "Leases on several servers".bold().underline(),
+        shared.len()
+    );
+    for conflict in shared.iter() {
+        let sources: BTreeSet<&str> = conflict.leases.iter().map(|x| x.source.as_str()).collect();
+        println!(
+            "{} {} ({})",
+            "info:".cyan(),
+            conflict.title,
+            sources.into_iter().collect::<Vec<&str>>().join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lease(source: &str, ip: &str, mac: &str, hostname: &str, state: &str) -> SourcedLease {
+        SourcedLease {
+            source: source.to_string(),
+            lease: serde_json::from_value(json!({
+                "ip": ip,
+                "hardware-ethernet": mac,
+                "client-hostname": hostname,
+                "binding-state": state
+            }))
+            .unwrap(),
+        }
+    }
+
+    fn titles(conflicts: &[Conflict]) -> Vec<String> {
+        conflicts.iter().map(|x| x.title.clone()).collect()
+    }
+
+    #[test]
+    fn mac_with_several_ips() {
+        let leases = vec![
+            lease("a", "10.0.1.120", "00:1A:2B:3c:4d:5e", "laptop", "active"),
+            lease("a", "10.0.1.121", "00:1a:2b:3c:4d:5e", "laptop", "active"),
+            lease("a", "10.0.1.110", "00:1a:2b:3c:4d:5e", "laptop", "free"),
+        ];
+        let conflicts = conflicts_in(&leases);
+        assert_eq!(
+            titles(&conflicts.macs),
+            vec!["MAC 00:1a:2b:3c:4d:5e holds 2 active IPs"]
+        );
+        assert_eq!(conflicts.macs[0].leases.len(), 2);
+        assert!(conflicts.hostnames.is_empty());
+        assert!(conflicts.ips.is_empty());
+    }
+
+    #[test]
+    fn hostname_on_several_macs() {
+        let leases = vec![
+            lease("a", "10.0.1.120", "00:1a:2b:3c:4d:5e", "Printer", "active"),
+            lease("a", "10.0.1.121", "da:a1:19:00:11:22", "printer", "active"),
+            lease("a", "10.0.1.122", "52:54:00:aa:bb:cc", "", "active"),
+            lease("a", "10.0.1.123", "52:54:00:aa:bb:cd", "", "active"),
+        ];
+        let conflicts = conflicts_in(&leases);
+        assert_eq!(
+            titles(&conflicts.hostnames),
+            vec!["Hostname printer is used by 2 MACs"]
+        );
+        assert!(conflicts.macs.is_empty());
+    }
+
+    #[test]
+    fn ip_leased_to_several_macs() {
+        let leases = vec![
+            lease("a", "10.0.1.120", "00:1a:2b:3c:4d:5e", "one", "active"),
+            lease("b", "10.0.1.120", "da:a1:19:00:11:22", "two", "active"),
+        ];
+        let conflicts = conflicts_in(&leases);
+        assert_eq!(
+            titles(&conflicts.ips),
+            vec!["IP 10.0.1.120 is leased to 2 MACs"]
+        );
+        assert!(conflicts.shared.is_empty());
+    }
+
+    #[test]
+    fn lease_shared_by_failover_peers() {
+        let leases = vec![
+            lease(
+                "primary",
+                "10.0.1.120",
+                "00:1a:2b:3c:4d:5e",
+                "laptop",
+                "active",
+            ),
+            lease(
+                "secondary",
+                "10.0.1.120",
+                "00:1A:2B:3C:4D:5E",
+                "laptop",
+                "active",
+            ),
+        ];
+        let conflicts = conflicts_in(&leases);
+        assert!(conflicts.macs.is_empty());
+        assert!(conflicts.hostnames.is_empty());
+        assert!(conflicts.ips.is_empty());
+        assert_eq!(
+            titles(&conflicts.shared),
+            vec!["10.0.1.120 00:1a:2b:3c:4d:5e is reported by 2 profiles"]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use super::error::DhcpctlError;
+use serde_json::Value;
+
+// Statements and options with a text value, which has to be quoted.
+const TEXT_VALUES: &[&str] = &[
+    "bootfile-name",
+    "ddns-domainname",
+    "ddns-hostname",
+    "domain-name",
+    "filename",
+    "host-name",
+    "root-path",
+    "server-name",
+    "tftp-server-name",
+];
+
+// A statement like `fixed-address 10.0.1.5;` or a block like
+// `host printer { ... }`, where the words are everything before the `{`.
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub words: Vec<String>,
+    pub block: Option<Vec<Statement>>,
+}
+
+impl Statement {
+    pub fn keyword(&self) -> &str {
+        self.words.first().map(|x| x.as_str()).unwrap_or_default()
+    }
+
+    // The words after the first `skip` ones, eg. the value of an option.
+    pub fn value(&self, skip: usize) -> String {
+        self.words.get(skip..).unwrap_or_default().join(" ")
+    }
+}
+
+enum Token {
+    Word(String),
+    End,
+    Open,
+    Close,
+}
+
+// Quoted strings are kept as one word without the quotes and comments run
+// from `#` to the end of the line.
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => while chars.next_if(|x| *x != '\n').is_some() {},
+            ';' => tokens.push(Token::End),
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '"' => {
+                let mut word = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        _ => word.push(c),
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+            c if c.is_whitespace() => {}
+            _ => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|x| !x.is_whitespace() && !";{}\"#".contains(*x))
+                {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    tokens
+}
+
+pub fn parse(text: &str) -> Result<Vec<Statement>, DhcpctlError> {
+    let mut stack: Vec<(Vec<String>, Vec<Statement>)> = vec![(Vec::new(), Vec::new())];
+    let mut words: Vec<String> = Vec::new();
+
+    for token in tokenize(text) {
+        match token {
+            Token::Word(word) => words.push(word),
+            Token::End => {
+                if !words.is_empty() {
+                    let statement = Statement {
+                        words: std::mem::take(&mut words),
+                        block: None,
+                    };
+                    stack.last_mut().unwrap().1.push(statement);
+                }
+            }
+            Token::Open => stack.push((std::mem::take(&mut words), Vec::new())),
+            Token::Close => {
+                if !words.is_empty() {
+                    return Err(DhcpctlError::DhcpdConf(format!(
+                        "missing ';' after '{}'",
+                        words.join(" ")
+                    )));
+                }
+                if stack.len() == 1 {
+                    return Err(DhcpctlError::DhcpdConf("unexpected '}'".to_string()));
+                }
+                let (words, body) = stack.pop().unwrap();
+                stack.last_mut().unwrap().1.push(Statement {
+                    words,
+                    block: Some(body),
+                });
+            }
+        }
+    }
+
+    if !words.is_empty() {
+        return Err(DhcpctlError::DhcpdConf(format!(
+            "missing ';' after '{}'",
+            words.join(" ")
+        )));
+    }
+    if stack.len() > 1 {
+        return Err(DhcpctlError::DhcpdConf(format!(
+            "block '{}' is not closed",
+            stack.last().unwrap().0.join(" ")
+        )));
+    }
+    Ok(stack.pop().unwrap().1)
+}
+
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Names like host names are only quoted when they have to be.
+pub fn word(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || "-_.:".contains(x))
+    {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+pub fn format_value(name: &str, value: &Value) -> String {
+    match value {
+        Value::Array(x) => x
+            .iter()
+            .map(|x| format_value(name, x))
+            .collect::<Vec<String>>()
+            .join(", "),
+        Value::String(x) if TEXT_VALUES.contains(&name) => quote(x),
+        Value::String(x) => x.clone(),
+        x => x.to_string(),
+    }
+}
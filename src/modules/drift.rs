@@ -0,0 +1,580 @@
+use super::config::{self, ConfyConfig, Profile};
+use super::dhcpd_conf::{self, Statement};
+use super::error::DhcpctlError;
+use super::globals::Globals;
+use super::hosts;
+use super::plan;
+use super::render;
+use super::reqwest_handler;
+use super::scopes::Scope;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// Options dhcpd takes as a comma separated list, which the API has as an
+// array.
+const LIST_OPTIONS: &[&str] = &["domain-name-servers"];
+
+// The part of the configuration that is compared, also the format of a
+// saved snapshot.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Reference {
+    #[serde(default)]
+    pub globals: Globals,
+    #[serde(default)]
+    pub scopes: Vec<Scope>,
+}
+
+enum Presence {
+    Both,
+    LiveOnly,
+    ReferenceOnly,
+}
+
+struct Drift {
+    kind: &'static str,
+    label: String,
+    presence: Presence,
+    fields: Vec<(String, Option<String>, Option<String>)>,
+}
+
+fn option_value(name: &str, value: String) -> Value {
+    if LIST_OPTIONS.contains(&name) {
+        Value::Array(
+            value
+                .split(',')
+                .map(|x| Value::String(x.trim().to_string()))
+                .collect(),
+        )
+    } else {
+        Value::String(value)
+    }
+}
+
+fn push(fields: &mut Map<String, Value>, name: &str, value: Value) {
+    if let Value::Array(items) = fields
+        .entry(name)
+        .or_insert_with(|| Value::Array(Vec::new()))
+    {
+        items.push(value);
+    }
+}
+
+// Plain statements of a block as the API would have them. Blocks are left to
+// the caller.
+fn fields(body: &[Statement]) -> Map<String, Value> {
+    let mut fields = Map::new();
+    for statement in body.iter().filter(|x| x.block.is_none()) {
+        match statement.words.as_slice() {
+            [keyword, name, ..] if keyword == "option" => {
+                let options = fields
+                    .entry("options")
+                    .or_insert_with(|| Value::Object(Map::new()));
+                if let Value::Object(options) = options {
+                    options.insert(name.clone(), option_value(name, statement.value(2)));
+                }
+            }
+            // Also `range dynamic-bootp start end;` and single address ranges.
+            [keyword, .., start, end] if keyword == "range" => {
+                let start = if start == "dynamic-bootp" { end } else { start };
+                push(
+                    &mut fields,
+                    "range",
+                    serde_json::json!({ "start": start, "end": end }),
+                );
+            }
+            [keyword, address] if keyword == "range" => {
+                push(
+                    &mut fields,
+                    "range",
+                    serde_json::json!({ "start": address, "end": address }),
+                );
+            }
+            [keyword, rule @ ..] if keyword == "allow" || keyword == "deny" => {
+                push(&mut fields, keyword, Value::String(rule.join(" ")));
+            }
+            [not, keyword] if not == "not" => {
+                fields.insert(keyword.clone(), Value::Bool(false));
+            }
+            [keyword] => {
+                fields.insert(keyword.clone(), Value::Bool(true));
+            }
+            [keyword, ..] => {
+                fields.insert(keyword.clone(), Value::String(statement.value(1)));
+            }
+            [] => {}
+        }
+    }
+    fields
+}
+
+// Subnets can be declared at the top level or inside shared-network and
+// group blocks. Hosts, classes and failover peers aren't compared.
+fn collect_scopes(
+    statements: &[Statement],
+    shared_network: Option<&str>,
+    scopes: &mut Vec<Scope>,
+) -> Result<(), DhcpctlError> {
+    for statement in statements.iter() {
+        let body = match &statement.block {
+            Some(body) => body,
+            None => continue,
+        };
+        match statement.words.as_slice() {
+            [keyword, ip, _, mask] if keyword == "subnet" => {
+                let mut scope = fields(body);
+                scope.insert("ip".to_string(), Value::String(ip.clone()));
+                scope.insert("subnet".to_string(), Value::String(mask.clone()));
+                if let Some(name) = shared_network {
+                    scope.insert(
+                        "shared-network".to_string(),
+                        Value::String(name.to_string()),
+                    );
+                }
+                let pools: Vec<Value> = body
+                    .iter()
+                    .filter(|x| x.keyword() == "pool")
+                    .filter_map(|x| x.block.as_ref())
+                    .map(|x| Value::Object(fields(x)))
+                    .collect();
+                if !pools.is_empty() {
+                    scope.insert("pools".to_string(), Value::Array(pools));
+                }
+                scopes.push(serde_json::from_value(Value::Object(scope))?);
+            }
+            [keyword, name] if keyword == "shared-network" => {
+                collect_scopes(body, Some(name), scopes)?
+            }
+            [keyword, ..] if keyword == "group" => collect_scopes(body, shared_network, scopes)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_reference(text: &str) -> Result<Reference, DhcpctlError> {
+    let statements = dhcpd_conf::parse(text)?;
+    let mut scopes = Vec::new();
+    collect_scopes(&statements, None, &mut scopes)?;
+    Ok(Reference {
+        globals: serde_json::from_value(Value::Object(fields(&statements)))?,
+        scopes,
+    })
+}
+
+// A snapshot from `drift --save` when the file ends in .json, a network
+// definition for `render` when it ends in .yaml, .yml or .toml and a
+// dhcpd.conf otherwise.
+fn load_reference(file: &Path) -> Result<Reference, DhcpctlError> {
+    match file.extension().and_then(|x| x.to_str()) {
+        Some("json") => Ok(serde_json::from_str(&fs::read_to_string(file)?)?),
+        Some("yaml") | Some("yml") | Some("toml") => {
+            let network = render::load_network(file)?;
+            Ok(Reference {
+                globals: network.globals,
+                scopes: network.scopes,
+            })
+        }
+        _ => parse_reference(&fs::read_to_string(file)?),
+    }
+}
+
+async fn fetch_reference(profile: &Profile) -> Result<Reference, DhcpctlError> {
+    let (globals, scopes) = tokio::try_join!(
+        reqwest_handler::run_on(profile, "/config/globals"),
+        reqwest_handler::run_on(profile, "/config/scopes")
+    )?;
+    Ok(Reference { globals, scopes })
+}
+
+// Masks dhcpd derives and the order of the ranges don't count as drift.
+fn normalize(reference: &mut Reference) {
+    for scope in reference.scopes.iter_mut() {
+        render::derive_masks(scope);
+        scope.ranges.sort_by_key(|x| x.bounds());
+        for pool in scope.pools.iter_mut() {
+            pool.ranges.sort_by_key(|x| x.bounds());
+        }
+    }
+}
+
+// Values are compared as text, so `3600` from a dhcpd.conf matches "3600"
+// from the API.
+fn text(value: &Value) -> String {
+    hosts::value_text(value)
+        .split(',')
+        .map(|x| x.trim())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+fn leaves<T: Serialize>(value: &T) -> Result<BTreeMap<String, String>, DhcpctlError> {
+    let mut leaves = BTreeMap::new();
+    plan::flatten("", &serde_json::to_value(value)?, &mut leaves);
+    Ok(leaves.iter().map(|(k, v)| (k.clone(), text(v))).collect())
+}
+
+fn diff<T: Serialize>(
+    kind: &'static str,
+    label: String,
+    live: &T,
+    reference: &T,
+) -> Result<Option<Drift>, DhcpctlError> {
+    let (live, reference) = (leaves(live)?, leaves(reference)?);
+    let mut names: Vec<&String> = live.keys().chain(reference.keys()).collect();
+    names.sort();
+    names.dedup();
+    let fields: Vec<(String, Option<String>, Option<String>)> = names
+        .into_iter()
+        .filter(|x| live.get(*x) != reference.get(*x))
+        .map(|x| (x.clone(), live.get(x).cloned(), reference.get(x).cloned()))
+        .collect();
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Drift {
+        kind,
+        label,
+        presence: Presence::Both,
+        fields,
+    }))
+}
+
+// A scope whose netmask changed is a different subnet, so it shows up on both
+// sides instead of being compared with the old one.
+fn same_subnet(a: &Scope, b: &Scope) -> bool {
+    a.ip == b.ip && a.subnet == b.subnet
+}
+
+fn compare(live: &Reference, reference: &Reference) -> Result<Vec<Drift>, DhcpctlError> {
+    let mut drifts = Vec::new();
+    drifts.extend(diff(
+        "globals",
+        String::new(),
+        &live.globals,
+        &reference.globals,
+    )?);
+
+    for scope in live.scopes.iter() {
+        match reference.scopes.iter().find(|x| same_subnet(x, scope)) {
+            Some(other) => drifts.extend(diff("scope", scope.cidr(), scope, other)?),
+            None => drifts.push(Drift {
+                kind: "scope",
+                label: scope.cidr(),
+                presence: Presence::LiveOnly,
+                fields: Vec::new(),
+            }),
+        }
+    }
+    for scope in reference.scopes.iter() {
+        if !live.scopes.iter().any(|x| same_subnet(x, scope)) {
+            drifts.push(Drift {
+                kind: "scope",
+                label: scope.cidr(),
+                presence: Presence::ReferenceOnly,
+                fields: Vec::new(),
+            });
+        }
+    }
+    Ok(drifts)
+}
+
+fn print_drifts(drifts: &[Drift], name: &str) {
+    let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "(unset)".to_string());
+    for drift in drifts.iter() {
+        let title = format!("{} {}", drift.kind, drift.label);
+        match drift.presence {
+            Presence::Both => println!("{} {}", "~".yellow().bold(), title.trim().bold()),
+            Presence::LiveOnly => println!(
+                "{} {} is only on the live server",
+                "-".red().bold(),
+                title.bold()
+            ),
+            Presence::ReferenceOnly => println!(
+                "{} {} is only in {}",
+                "+".green().bold(),
+                title.bold(),
+                name
+            ),
+        }
+        for (field, live, reference) in drift.fields.iter() {
+            println!(
+                "    {} {}: live {}, {} {}",
+                "~".yellow(),
+                field,
+                show(live).red(),
+                name,
+                show(reference).green()
+            );
+        }
+    }
+}
+
+// Writes the live globals and scopes as a snapshot to compare with later.
+pub async fn save_snapshot(file: &Path) -> Result<(), DhcpctlError> {
+    let live = fetch_reference(&config::load_profile()?).await?;
+    fs::write(file, serde_json::to_string_pretty(&live)?)?;
+    println!(
+        "Saved the globals and {} scopes to {}",
+        live.scopes.len(),
+        file.display().to_string().green()
+    );
+    Ok(())
+}
+
+pub enum Against<'a> {
+    File(&'a Path),
+    Profile(&'a str),
+}
+
+// Any drift is an error, so scheduled checks can act on the exit code.
+pub async fn drift(against: Against<'_>) -> Result<(), DhcpctlError> {
+    let (mut reference, name) = match against {
+        Against::File(file) => (load_reference(file)?, file.display().to_string()),
+        Against::Profile(profile) => {
+            let conf: ConfyConfig = config::load_config()?;
+            (
+                fetch_reference(&conf.profile(profile)?).await?,
+                format!("profile {}", profile),
+            )
+        }
+    };
+    let mut live = fetch_reference(&config::load_profile()?).await?;
+    normalize(&mut live);
+    normalize(&mut reference);
+
+    let drifts = compare(&live, &reference)?;
+    if drifts.is_empty() {
+        println!("No drift. The live configuration matches {}.", name);
+        return Ok(());
+    }
+    print_drifts(&drifts, &name);
+    println!();
+    Err(DhcpctlError::DriftFound(drifts.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CONF: &str = r#"
+        # Generated for the drift tests
+        authoritative;
+        not ddns-updates;
+        default-lease-time 600;
+        max-lease-time 7200;
+        option domain-name "example.org";
+        option domain-name-servers 10.0.0.2, 10.0.0.3;
+
+        shared-network "vlan10" {
+          group {
+            subnet 10.0.1.0 netmask 255.255.255.0 {
+              range 10.0.1.100 10.0.1.150;
+              range dynamic-bootp 10.0.1.160 10.0.1.199;
+              option routers 10.0.1.1;
+              option domain-name-servers 10.0.0.2;
+              host printer { hardware ethernet 00:1a:2b:3c:4d:5e; fixed-address 10.0.1.20; }
+            }
+          }
+          subnet 10.0.2.0 netmask 255.255.254.0 {
+            option routers 10.0.2.1;
+            option bootfile-name "pxelinux.0";
+            option tftp-server-name "10.0.0.5";
+            next-server 10.0.0.5;
+            default-lease-time 3600;
+            pool {
+              deny unknown-clients;
+              range 10.0.2.10;
+              range 10.0.2.20 10.0.3.250;
+            }
+          }
+        }
+
+        subnet 10.0.9.0 netmask 255.255.255.0 {
+          option routers 10.0.9.1;
+        }
+    "#;
+
+    // The same configuration as the API returns it.
+    fn snapshot() -> Reference {
+        serde_json::from_value(json!({
+            "globals": {
+                "authoritative": true,
+                "ddns-updates": false,
+                "default-lease-time": "600",
+                "max-lease-time": "7200",
+                "options": {
+                    "domain-name": "example.org",
+                    "domain-name-servers": ["10.0.0.2", "10.0.0.3"]
+                }
+            },
+            "scopes": [
+                {
+                    "ip": "10.0.1.0",
+                    "subnet": "255.255.255.0",
+                    "shared-network": "vlan10",
+                    "range": [
+                        {"start": "10.0.1.160", "end": "10.0.1.199"},
+                        {"start": "10.0.1.100", "end": "10.0.1.150"}
+                    ],
+                    "options": {
+                        "subnet-mask": "255.255.255.0",
+                        "broadcast-address": "10.0.1.255",
+                        "routers": "10.0.1.1",
+                        "domain-name-servers": ["10.0.0.2"]
+                    }
+                },
+                {
+                    "ip": "10.0.2.0",
+                    "subnet": "255.255.254.0",
+                    "shared-network": "vlan10",
+                    "options": {
+                        "routers": "10.0.2.1",
+                        "bootfile-name": "pxelinux.0",
+                        "tftp-server-name": "10.0.0.5"
+                    },
+                    "next-server": "10.0.0.5",
+                    "default-lease-time": "3600",
+                    "pools": [{
+                        "deny": ["unknown-clients"],
+                        "range": [
+                            {"start": "10.0.2.10", "end": "10.0.2.10"},
+                            {"start": "10.0.2.20", "end": "10.0.3.250"}
+                        ]
+                    }]
+                },
+                {
+                    "ip": "10.0.9.0",
+                    "subnet": "255.255.255.0",
+                    "options": {"routers": "10.0.9.1"}
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn drifts(mut live: Reference, mut reference: Reference) -> Vec<Drift> {
+        normalize(&mut live);
+        normalize(&mut reference);
+        compare(&live, &reference).unwrap()
+    }
+
+    #[test]
+    fn parses_the_dhcpd_conf() {
+        let reference = parse_reference(CONF).unwrap();
+        assert_eq!(reference.globals.authoritative, Some(true));
+        assert_eq!(reference.globals.extra["ddns-updates"], json!(false));
+        let scopes: Vec<(String, Option<String>)> = reference
+            .scopes
+            .iter()
+            .map(|x| (x.cidr(), x.shared_network.clone()))
+            .collect();
+        assert_eq!(
+            scopes,
+            vec![
+                ("10.0.1.0/24".to_string(), Some("vlan10".to_string())),
+                ("10.0.2.0/23".to_string(), Some("vlan10".to_string())),
+                ("10.0.9.0/24".to_string(), None),
+            ]
+        );
+        assert_eq!(reference.scopes[0].ranges[1].start, "10.0.1.160");
+        assert_eq!(reference.scopes[1].pools[0].ranges[0].end, "10.0.2.10");
+    }
+
+    #[test]
+    fn dhcpd_conf_matches_the_equivalent_snapshot() {
+        let drifts = drifts(snapshot(), parse_reference(CONF).unwrap());
+        let fields: Vec<&(String, Option<String>, Option<String>)> =
+            drifts.iter().flat_map(|x| x.fields.iter()).collect();
+        assert!(drifts.is_empty(), "unexpected drift: {:?}", fields);
+    }
+
+    // Kind, field, label, live and reference value.
+    type FieldRow<'a> = (&'a str, &'a str, String, Option<String>, Option<String>);
+
+    #[test]
+    fn reports_changed_fields() {
+        let conf = CONF
+            .replace("option routers 10.0.1.1;", "option routers 10.0.1.254;")
+            .replace("max-lease-time 7200;", "max-lease-time 3600;");
+        let drifts = drifts(snapshot(), parse_reference(&conf).unwrap());
+        let fields: Vec<FieldRow> = drifts
+            .iter()
+            .flat_map(|x| {
+                x.fields.iter().map(move |(name, live, reference)| {
+                    (
+                        x.kind,
+                        name.as_str(),
+                        x.label.clone(),
+                        live.clone(),
+                        reference.clone(),
+                    )
+                })
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                (
+                    "globals",
+                    "max-lease-time",
+                    String::new(),
+                    Some("7200".to_string()),
+                    Some("3600".to_string())
+                ),
+                (
+                    "scope",
+                    "options.routers",
+                    "10.0.1.0/24".to_string(),
+                    Some("10.0.1.1".to_string()),
+                    Some("10.0.1.254".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_scopes_on_one_side_only() {
+        let mut live = snapshot();
+        live.scopes.remove(2);
+        let mut reference = parse_reference(CONF).unwrap();
+        reference.scopes.remove(0);
+        let drifts = drifts(live, reference);
+        let presence: Vec<(String, bool)> = drifts
+            .iter()
+            .map(|x| (x.label.clone(), matches!(x.presence, Presence::LiveOnly)))
+            .collect();
+        assert_eq!(
+            presence,
+            vec![
+                ("10.0.1.0/24".to_string(), true),
+                ("10.0.9.0/24".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_netmask_is_a_different_scope() {
+        let conf = CONF.replace(
+            "subnet 10.0.9.0 netmask 255.255.255.0",
+            "subnet 10.0.9.0 netmask 255.255.254.0",
+        );
+        let drifts = drifts(snapshot(), parse_reference(&conf).unwrap());
+        let presence: Vec<(String, bool)> = drifts
+            .iter()
+            .filter(|x| x.kind == "scope")
+            .map(|x| (x.label.clone(), matches!(x.presence, Presence::LiveOnly)))
+            .collect();
+        assert_eq!(
+            presence,
+            vec![
+                ("10.0.9.0/24".to_string(), true),
+                ("10.0.9.0/23".to_string(), false),
+            ]
+        );
+    }
+}
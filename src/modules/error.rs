@@ -1,21 +1,40 @@
+use super::config::DEFAULT_PROFILE;
 use chrono::format::ParseError;
 use confy::ConfyError;
 use reqwest::header::InvalidHeaderValue;
 use reqwest::Error as ReqwestError;
 use std::error::Error;
 use std::fmt;
+use std::io;
 
 #[derive(Debug)]
 pub enum DhcpctlError {
     MissingArguments,
     MissingConfigFile,
     MissingUrl,
+    UnknownProfile(String),
+    ProfileInUse(String),
     NotValidCIDR,
+    NotValidBroker,
     Reqwest(ReqwestError),
     Confy(ConfyError),
     ParseError(ParseError),
     InvalidHeaderValue(InvalidHeaderValue),
     BadStatusCode(String),
+    ProblemsFound(usize),
+    DriftFound(usize),
+    UnreachableProfiles(Vec<String>),
+    Io(io::Error),
+    Json(serde_json::Error),
+    Mqtt(rumqttc::ClientError),
+    Regex(regex::Error),
+    InvalidDateFormat(String),
+    DhcpdConf(String),
+    InvalidHost(String),
+    NotValidTarget(String),
+    ReadOnlyProfile(String),
+    Yaml(serde_yaml::Error),
+    Toml(toml::de::Error),
 }
 
 impl Error for DhcpctlError {}
@@ -23,15 +42,33 @@ impl Error for DhcpctlError {}
 impl fmt::Display for DhcpctlError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            DhcpctlError::MissingArguments => write!(f, "You need to provide either an url, token or both."),
+            DhcpctlError::MissingArguments => write!(f, "You need to provide an url, token, date format or read-only setting."),
             DhcpctlError::MissingConfigFile => write!(f, "Config file not found."),
             DhcpctlError::MissingUrl => write!(f, "The URL for the ISC DHCP API is missing. Set it with 'dhcpctl config set --url https://ip-or-domain-name'."),
+            DhcpctlError::UnknownProfile(e) => write!(f, "No profile named '{}'. Create it with 'dhcpctl --profile {} config set --url https://ip-or-domain-name'.", e, e),
+            DhcpctlError::ProfileInUse(e) if e == DEFAULT_PROFILE => write!(f, "Cannot remove the default profile, it is the top level URL and token in the config file."),
+            DhcpctlError::ProfileInUse(e) => write!(f, "Cannot remove the active profile '{}'. Select another one with --profile or DHCPCTL_PROFILE first.", e),
             DhcpctlError::NotValidCIDR => write!(f, "Not a valid CIDR."),
+            DhcpctlError::NotValidBroker => write!(f, "Not a valid MQTT broker, eg. tcp://localhost:1883."),
             DhcpctlError::Reqwest(e) => write!(f, "{}", e.to_string()),
             DhcpctlError::Confy(e) => write!(f, "[config-file] {}", e.to_string()),
             DhcpctlError::ParseError(e) => write!(f, "{}", e.to_string()),
             DhcpctlError::InvalidHeaderValue(e) => write!(f, "{}", e.to_string()),
             DhcpctlError::BadStatusCode(e) => write!(f, "{}", e),
+            DhcpctlError::ProblemsFound(e) => write!(f, "Found {} problem(s) at or above the --fail-on severity.", e),
+            DhcpctlError::DriftFound(e) => write!(f, "Found drift in {} item(s).", e),
+            DhcpctlError::UnreachableProfiles(e) => write!(f, "Could not load the profile(s) {}, the results are incomplete.", e.join(", ")),
+            DhcpctlError::Io(e) => write!(f, "{}", e),
+            DhcpctlError::Json(e) => write!(f, "{}", e),
+            DhcpctlError::Mqtt(e) => write!(f, "[mqtt] {}", e),
+            DhcpctlError::Regex(e) => write!(f, "[fingerprints] {}", e),
+            DhcpctlError::InvalidDateFormat(e) => write!(f, "'{}' is not a valid strftime date format.", e),
+            DhcpctlError::DhcpdConf(e) => write!(f, "[dhcpd.conf] {}", e),
+            DhcpctlError::InvalidHost(e) => write!(f, "[hosts] {}", e),
+            DhcpctlError::NotValidTarget(e) => write!(f, "'{}' is not an IP or MAC address.", e),
+            DhcpctlError::Yaml(e) => write!(f, "[yaml] {}", e),
+            DhcpctlError::Toml(e) => write!(f, "[toml] {}", e),
+            DhcpctlError::ReadOnlyProfile(e) => write!(f, "The profile '{}' is read-only. Allow changes with 'dhcpctl --profile {} config set --read-only false'.", e, e),
         }
     }
 }
@@ -60,6 +97,42 @@ impl From<InvalidHeaderValue> for DhcpctlError {
     }
 }
 
+impl From<io::Error> for DhcpctlError {
+    fn from(error: io::Error) -> Self {
+        DhcpctlError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for DhcpctlError {
+    fn from(error: serde_json::Error) -> Self {
+        DhcpctlError::Json(error)
+    }
+}
+
+impl From<rumqttc::ClientError> for DhcpctlError {
+    fn from(error: rumqttc::ClientError) -> Self {
+        DhcpctlError::Mqtt(error)
+    }
+}
+
+impl From<regex::Error> for DhcpctlError {
+    fn from(error: regex::Error) -> Self {
+        DhcpctlError::Regex(error)
+    }
+}
+
+impl From<serde_yaml::Error> for DhcpctlError {
+    fn from(error: serde_yaml::Error) -> Self {
+        DhcpctlError::Yaml(error)
+    }
+}
+
+impl From<toml::de::Error> for DhcpctlError {
+    fn from(error: toml::de::Error) -> Self {
+        DhcpctlError::Toml(error)
+    }
+}
+
 impl From<DhcpctlError> for String {
     fn from(error: DhcpctlError) -> Self {
         error.to_string()
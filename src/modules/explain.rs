@@ -0,0 +1,310 @@
+use super::error::DhcpctlError;
+use super::globals::Globals;
+use super::hosts::{self, Host};
+use super::lease_time::LeaseTime;
+use super::leases::{self, Lease};
+use super::reqwest_handler;
+use super::scopes::{self, Scope};
+use super::watcher;
+use colored::Colorize;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+
+enum Target {
+    Ip(String),
+    Mac(String),
+    Hostname(String),
+}
+
+impl Target {
+    fn parse(target: &str) -> Self {
+        let hex_pairs: Vec<&str> = target.split(&[':', '-'][..]).collect();
+        if target.parse::<Ipv4Addr>().is_ok() {
+            Target::Ip(target.to_string())
+        } else if hex_pairs.len() == 6
+            && hex_pairs
+                .iter()
+                .all(|x| x.len() == 2 && u8::from_str_radix(x, 16).is_ok())
+        {
+            Target::Mac(hex_pairs.join(":").to_lowercase())
+        } else {
+            Target::Hostname(target.to_lowercase())
+        }
+    }
+
+    fn matches(&self, lease: &Lease) -> bool {
+        match self {
+            Target::Ip(ip) => &lease.ip == ip,
+            Target::Mac(mac) => &lease.hardware_ethernet.to_lowercase() == mac,
+            Target::Hostname(hostname) => lease
+                .client_hostname
+                .as_ref()
+                .map(|x| &x.to_lowercase() == hostname)
+                .unwrap_or(false),
+        }
+    }
+
+    fn matches_host(&self, host: &Host) -> bool {
+        match self {
+            Target::Ip(ip) => &host.fixed_address == ip,
+            Target::Mac(mac) => host.matches_mac(mac),
+            Target::Hostname(hostname) => host.name.eq_ignore_ascii_case(hostname),
+        }
+    }
+}
+
+fn print_field(label: &str, value: &str) {
+    println!("{} {}", format!("{}:", label).bold(), value);
+}
+
+// The vendor is only shown when it was looked up.
+fn print_lease(lease: &Lease, vendor: Option<Option<&String>>) {
+    println!("\n{}", "Lease".underline().bold());
+    print_field("IP", &lease.ip);
+    print_field("MAC Address", &lease.hardware_ethernet);
+    print_field(
+        "Hostname",
+        lease.client_hostname.as_deref().unwrap_or("Not set"),
+    );
+    print_field("Status", &lease.binding_state);
+    print_field("Starts", &LeaseTime::parse(&lease.starts).to_string());
+    print_field("Ends", &LeaseTime::parse(&lease.ends).to_string());
+    if let Some(remaining) = leases::time_remaining(&lease.ends) {
+        print_field("Remaining", &remaining);
+    }
+    print_field(
+        "Vendor Identifier",
+        lease
+            .set_vendor_class_identifier
+            .as_deref()
+            .unwrap_or("Not set"),
+    );
+    if let Some(vendor) = vendor {
+        print_field(
+            "MAC Vendor",
+            vendor.map(|x| x.as_str()).unwrap_or("Unknown"),
+        );
+    }
+    print_field("MAC Type", leases::mac_type(&lease.hardware_ethernet));
+}
+
+// Scope settings win over the globals, the same way dhcpd resolves them.
+fn print_scope(scope: &Scope, ips: &[&String], globals: &Globals) {
+    let global_options = globals.options.as_ref();
+    let inherited = |scope_value: &Option<String>, global_value: Option<String>| match (
+        scope_value,
+        global_value,
+    ) {
+        (Some(x), _) => x.clone(),
+        (None, Some(x)) => format!("{} (global)", x),
+        (None, None) => "Not set".to_string(),
+    };
+
+    println!("\n{}", "Scope".underline().bold());
+    print_field("Network ID", &scope.cidr());
+    if let Some(shared_network) = &scope.shared_network {
+        print_field("Shared Network", shared_network);
+    }
+    let position = |ip: &str| {
+        if scope.range_contains(ip) {
+            "inside range"
+        } else {
+            "outside range"
+        }
+    };
+    let positions = match ips {
+        [ip] => position(ip).to_string(),
+        _ => ips
+            .iter()
+            .map(|x| format!("{} {}", x, position(x)))
+            .collect::<Vec<String>>()
+            .join(", "),
+    };
+    print_field(
+        "IPs",
+        &ips.iter()
+            .map(|x| x.as_str())
+            .collect::<Vec<&str>>()
+            .join(", "),
+    );
+    print_field(
+        "Scope Range",
+        &format!("{} ({})", scope.range_label(), positions),
+    );
+    print_field("Gateway", &scope.options.routers);
+    print_field(
+        "DNS Servers",
+        &inherited(
+            &scope
+                .options
+                .domain_name_servers
+                .as_ref()
+                .map(|x| x.join(",")),
+            global_options
+                .and_then(|x| x.domain_name_servers.as_ref())
+                .map(|x| x.join(",")),
+        ),
+    );
+    print_field(
+        "Domain Name",
+        &inherited(
+            &scope.options.domain_name,
+            global_options.and_then(|x| x.domain_name.clone()),
+        ),
+    );
+    print_field(
+        "Default Lease Time",
+        &inherited(
+            &scope.default_lease_time,
+            globals.default_lease_time.clone(),
+        ),
+    );
+    print_field(
+        "Max Lease Time",
+        &inherited(&scope.max_lease_time, globals.max_lease_time.clone()),
+    );
+
+    if scope.options.tftp_server_name.is_some()
+        || scope.options.bootfile_name.is_some()
+        || scope.next_server.is_some()
+    {
+        print_field(
+            "TFTP Server",
+            scope
+                .options
+                .tftp_server_name
+                .as_deref()
+                .unwrap_or("Not set"),
+        );
+        print_field(
+            "Bootfile",
+            scope.options.bootfile_name.as_deref().unwrap_or("Not set"),
+        );
+        print_field(
+            "Next Server",
+            scope.next_server.as_deref().unwrap_or("Not set"),
+        );
+    }
+}
+
+fn print_history(macs: &HashSet<String>, ips: &HashSet<String>) -> Result<(), DhcpctlError> {
+    let history: Vec<watcher::LeaseEvent> = watcher::load_history()?
+        .into_iter()
+        .filter(|x| macs.contains(&x.mac) || ips.contains(&x.ip))
+        .collect();
+
+    println!("\n{}", "History".underline().bold());
+    if history.is_empty() {
+        println!("No events recorded. History is collected by 'dhcpctl daemon'.");
+    }
+    for event in history.iter() {
+        println!(
+            "{} {} {} {} {}",
+            event.timestamp,
+            event.event.to_string().bold(),
+            event.mac,
+            event.ip,
+            event
+                .previous
+                .as_ref()
+                .map(|x| format!("(was {})", x))
+                .unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+pub async fn explain(target: &str, mac_lookup: bool) -> Result<(), DhcpctlError> {
+    let (all_leases, scopes, globals): (Vec<Lease>, Vec<Scope>, Globals) = tokio::try_join!(
+        reqwest_handler::run("/leases/"),
+        reqwest_handler::run("/config/scopes"),
+        reqwest_handler::run("/config/globals")
+    )?;
+
+    let reservations = hosts::fetch_reservations().await?;
+
+    let target = Target::parse(target);
+    let mut matched: Vec<&Lease> = all_leases.iter().filter(|x| target.matches(x)).collect();
+    matched.sort_by_key(|x| Reverse(LeaseTime::parse(&x.starts)));
+    let reserved: Vec<&Host> = reservations
+        .iter()
+        .filter(|x| target.matches_host(x) || matched.iter().any(|lease| x.matches_lease(lease)))
+        .collect();
+
+    let mut ips: HashSet<String> = matched
+        .iter()
+        .map(|x| x.ip.clone())
+        .chain(
+            reserved
+                .iter()
+                .filter(|x| scopes::ip_to_u32(&x.fixed_address).is_some())
+                .map(|x| x.fixed_address.clone()),
+        )
+        .collect();
+    if let Target::Ip(ip) = &target {
+        ips.insert(ip.clone());
+    }
+    let macs: HashSet<String> = matched
+        .iter()
+        .map(|x| x.hardware_ethernet.to_lowercase())
+        .chain(reserved.iter().map(|x| x.hardware_ethernet.to_lowercase()))
+        .filter(|x| !x.is_empty())
+        .chain(match &target {
+            Target::Mac(mac) => Some(mac.clone()),
+            _ => None,
+        })
+        .collect();
+
+    if matched.is_empty() {
+        println!("No leases found");
+    }
+
+    let mut vendors = HashMap::new();
+    if mac_lookup && !matched.is_empty() {
+        println!(
+            "\n{}",
+            "The vendor lookup data is from https://macvendors.co. Too many requests could result in API limitations.".cyan()
+        );
+        let prefixes: HashSet<&str> = macs
+            .iter()
+            .filter(|x| x.len() >= 8)
+            .map(|x| &x[0..8])
+            .collect();
+        vendors = leases::get_vendors(&prefixes).await?;
+    }
+
+    for lease in matched.iter() {
+        let mac = lease.hardware_ethernet.to_lowercase();
+        let vendor = mac.get(0..8).and_then(|x| vendors.get(x));
+        print_lease(lease, if mac_lookup { Some(vendor) } else { None });
+    }
+
+    for host in reserved.iter() {
+        println!("\n{}", "Reservation".underline().bold());
+        hosts::print_host_detail(host);
+    }
+
+    // Each scope is printed once with all of the IPs it contains.
+    let mut sorted_ips: Vec<&String> = ips.iter().collect();
+    sorted_ips.sort_by_key(|x| scopes::ip_to_u32(x));
+    for scope in scopes.iter() {
+        let scope_ips: Vec<&String> = sorted_ips
+            .iter()
+            .copied()
+            .filter(|x| scope.contains(x))
+            .collect();
+        if !scope_ips.is_empty() {
+            print_scope(scope, &scope_ips, &globals);
+        }
+    }
+    for ip in sorted_ips
+        .iter()
+        .filter(|x| !scopes.iter().any(|s| s.contains(x)))
+    {
+        println!("\n{} {}", "No scope contains".bold(), ip);
+    }
+
+    print_history(&macs, &ips)?;
+    Ok(())
+}
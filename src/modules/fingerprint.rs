@@ -0,0 +1,293 @@
+use super::config;
+use super::error::DhcpctlError;
+use super::leases::{Lease, OutputFormat};
+use super::reqwest_handler;
+use cidr_utils::cidr::IpCidr;
+use prettytable::{cell, format, row, Table};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+
+const BUILTIN_RULES: &str = include_str!("fingerprints.json");
+const RULES_FILE: &str = "fingerprints.json";
+
+// A rule matches when every pattern it sets matches. Patterns are regular
+// expressions, `oui` is matched against the first three octets of the MAC.
+#[derive(Deserialize, Debug)]
+struct Rule {
+    #[serde(rename = "vendor-class")]
+    vendor_class: Option<String>,
+    hostname: Option<String>,
+    oui: Option<String>,
+    os: Option<String>,
+    family: Option<String>,
+    arch: Option<String>,
+}
+
+struct CompiledRule {
+    vendor_class: Option<Regex>,
+    hostname: Option<Regex>,
+    oui: Option<Regex>,
+    rule: Rule,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Device {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<&str> = [&self.os, &self.family, &self.arch]
+            .iter()
+            .filter_map(|x| x.as_deref())
+            .collect();
+        write!(f, "{}", parts.join(" / "))
+    }
+}
+
+fn matches(pattern: &Option<Regex>, value: Option<&str>) -> bool {
+    match pattern {
+        Some(regex) => value.map(|x| regex.is_match(x)).unwrap_or(false),
+        None => true,
+    }
+}
+
+pub struct Fingerprinter {
+    rules: Vec<CompiledRule>,
+}
+
+impl Fingerprinter {
+    // Rules in the user file next to the config file are tried before the
+    // built-in ones, so they can override or extend them.
+    pub fn load() -> Result<Self, DhcpctlError> {
+        let user_file = config::data_file(RULES_FILE)?;
+        let user_rules = if user_file.exists() {
+            serde_json::from_str::<Vec<Rule>>(&fs::read_to_string(user_file)?)?
+        } else {
+            Vec::new()
+        };
+        Self::from_rules(user_rules)
+    }
+
+    fn from_rules(user_rules: Vec<Rule>) -> Result<Self, DhcpctlError> {
+        let mut rules = user_rules;
+        rules.extend(serde_json::from_str::<Vec<Rule>>(BUILTIN_RULES)?);
+
+        let compile = |pattern: &Option<String>| -> Result<Option<Regex>, DhcpctlError> {
+            Ok(match pattern {
+                Some(x) => Some(Regex::new(x)?),
+                None => None,
+            })
+        };
+
+        let mut compiled = Vec::new();
+        for rule in rules {
+            compiled.push(CompiledRule {
+                vendor_class: compile(&rule.vendor_class)?,
+                hostname: compile(&rule.hostname)?,
+                // MACs are compared in lowercase, so rules may use either.
+                oui: compile(&rule.oui.as_ref().map(|x| format!("(?i)^(?:{})$", x)))?,
+                rule,
+            });
+        }
+        Ok(Fingerprinter { rules: compiled })
+    }
+
+    // Every attribute is taken from the first matching rule that sets it, so
+    // eg. the OS can come from the vendor class and the family from the OUI.
+    pub fn classify(&self, lease: &Lease) -> Device {
+        let mac = lease.hardware_ethernet.to_lowercase();
+        let mut device = Device::default();
+
+        for compiled in self.rules.iter() {
+            if !matches(
+                &compiled.vendor_class,
+                lease.set_vendor_class_identifier.as_deref(),
+            ) || !matches(&compiled.hostname, lease.client_hostname.as_deref())
+                || !matches(&compiled.oui, mac.get(0..8))
+            {
+                continue;
+            }
+            let rule = &compiled.rule;
+            if device.os.is_none() {
+                device.os = rule.os.clone();
+            }
+            if device.family.is_none() {
+                device.family = rule.family.clone();
+            }
+            if device.arch.is_none() {
+                device.arch = rule.arch.clone();
+            }
+        }
+        device
+    }
+}
+
+#[derive(Serialize)]
+struct DeviceCount<'a> {
+    #[serde(flatten)]
+    device: &'a Device,
+    count: usize,
+}
+
+fn table_format() -> format::TableFormat {
+    format::FormatBuilder::new()
+        .column_separator(' ')
+        .separator(
+            format::LinePosition::Title,
+            format::LineSeparator::new('-', ' ', ' ', ' '),
+        )
+        .padding(1, 1)
+        .build()
+}
+
+pub async fn device_summary(cidr: String, output: OutputFormat) -> Result<(), DhcpctlError> {
+    if !cidr.is_empty() && !IpCidr::is_ipv4_cidr(&cidr) {
+        return Err(DhcpctlError::NotValidCIDR);
+    }
+    let payload: Vec<Lease> = reqwest_handler::run(format!("/leases/{}", cidr).as_str()).await?;
+    let fingerprinter = Fingerprinter::load()?;
+
+    let mut groups: BTreeMap<Device, usize> = BTreeMap::new();
+    for lease in payload.iter().filter(|x| x.binding_state == "active") {
+        *groups.entry(fingerprinter.classify(lease)).or_default() += 1;
+    }
+    let mut counts: Vec<DeviceCount> = groups
+        .iter()
+        .map(|(device, count)| DeviceCount {
+            device,
+            count: *count,
+        })
+        .collect();
+    counts.sort_by_key(|x| Reverse(x.count));
+
+    if output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&counts)?);
+        return Ok(());
+    }
+    if counts.is_empty() {
+        println!("No active leases found");
+        return Ok(());
+    }
+
+    let not_set = String::from("Unknown");
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!(b -> "OS", b -> "Family", b -> "Arch", b -> "Leases"));
+    for entry in counts.iter() {
+        table.add_row(row!(
+            entry.device.os.as_ref().unwrap_or(&not_set),
+            entry.device.family.as_ref().unwrap_or(&not_set),
+            entry.device.arch.clone().unwrap_or_default(),
+            r -> entry.count,
+        ));
+    }
+    table.printstd();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lease(mac: &str, vendor_class: Option<&str>, hostname: Option<&str>) -> Lease {
+        serde_json::from_value(json!({
+            "ip": "10.0.1.120",
+            "hardware-ethernet": mac,
+            "set-vendor-class-identifier": vendor_class,
+            "client-hostname": hostname
+        }))
+        .unwrap()
+    }
+
+    fn device(os: Option<&str>, family: Option<&str>, arch: Option<&str>) -> Device {
+        Device {
+            os: os.map(str::to_string),
+            family: family.map(str::to_string),
+            arch: arch.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn builtin_rules() {
+        let fingerprinter = Fingerprinter::from_rules(Vec::new()).unwrap();
+        let classify = |vendor_class, hostname| {
+            fingerprinter.classify(&lease("00:1a:2b:3c:4d:5e", vendor_class, hostname))
+        };
+        assert_eq!(
+            classify(Some("MSFT 5.0"), None),
+            device(Some("Windows"), Some("Computer"), None)
+        );
+        assert_eq!(
+            classify(Some("android-dhcp-11"), None),
+            device(Some("Android"), Some("Phone"), None)
+        );
+        assert_eq!(
+            classify(Some("PXEClient:Arch:00007:UNDI:003016"), None),
+            device(None, Some("PXE boot"), Some("x64 UEFI"))
+        );
+        assert_eq!(
+            classify(Some("PXEClient:Arch:00000:UNDI:002001"), None),
+            device(None, Some("PXE boot"), Some("x86 BIOS"))
+        );
+        assert_eq!(
+            classify(None, Some("iPhone-Alice")),
+            device(Some("iOS"), Some("Phone"), None)
+        );
+        assert_eq!(classify(Some("unknown"), None), Device::default());
+    }
+
+    #[test]
+    fn oui_rules_ignore_case() {
+        let rules: Vec<Rule> =
+            serde_json::from_value(json!([{"oui": "AA:BB:CC", "family": "Printer"}])).unwrap();
+        let fingerprinter = Fingerprinter::from_rules(rules).unwrap();
+        for mac in ["aa:bb:cc:00:11:22", "AA:BB:CC:00:11:22"].iter() {
+            assert_eq!(
+                fingerprinter
+                    .classify(&lease(mac, None, None))
+                    .family
+                    .as_deref(),
+                Some("Printer")
+            );
+        }
+        assert_eq!(
+            fingerprinter
+                .classify(&lease("B8:27:EB:12:34:56", None, None))
+                .family
+                .as_deref(),
+            Some("Raspberry Pi")
+        );
+        assert_eq!(
+            fingerprinter.classify(&lease("", None, None)),
+            Device::default()
+        );
+    }
+
+    #[test]
+    fn user_rules_take_precedence() {
+        let rules: Vec<Rule> = serde_json::from_value(json!([
+            {"vendor-class": "^MSFT 5\\.0", "oui": "00:1a:2b", "family": "Kiosk"}
+        ]))
+        .unwrap();
+        let fingerprinter = Fingerprinter::from_rules(rules).unwrap();
+        assert_eq!(
+            fingerprinter.classify(&lease("00:1A:2B:3c:4d:5e", Some("MSFT 5.0"), None)),
+            device(Some("Windows"), Some("Kiosk"), None)
+        );
+        assert_eq!(
+            fingerprinter.classify(&lease("52:54:00:3c:4d:5e", Some("MSFT 5.0"), None)),
+            device(Some("Windows"), Some("Computer"), None)
+        );
+    }
+}
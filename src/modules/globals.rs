@@ -1,24 +1,39 @@
 use super::error::DhcpctlError;
 use super::reqwest_handler;
+use super::scopes::string_or_number;
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Globals {
     pub authoritative: Option<bool>,
-    #[serde(rename = "default-lease-time")]
+    #[serde(
+        rename = "default-lease-time",
+        default,
+        deserialize_with = "string_or_number"
+    )]
     pub default_lease_time: Option<String>,
-    #[serde(rename = "max-lease-time")]
+    #[serde(
+        rename = "max-lease-time",
+        default,
+        deserialize_with = "string_or_number"
+    )]
     pub max_lease_time: Option<String>,
     pub options: Option<Options>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Options {
     #[serde(rename = "domain-name")]
     pub domain_name: Option<String>,
     #[serde(rename = "domain-name-servers")]
     pub domain_name_servers: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
 pub async fn list_globals() -> Result<(), DhcpctlError> {
@@ -0,0 +1,502 @@
+use super::audit;
+use super::dhcpd_conf::{self, Statement};
+use super::error::DhcpctlError;
+use super::leases::{self, Lease};
+use super::reqwest_handler;
+use super::scopes::{self, Scope};
+use colored::Colorize;
+use prettytable::{cell, format, row, Table};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// A `host` declaration. Hosts identified by client identifier instead of MAC
+// have an empty `hardware_ethernet`, and `fixed_address` may be a DNS name.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Host {
+    pub name: String,
+    #[serde(rename = "hardware-ethernet", default)]
+    pub hardware_ethernet: String,
+    #[serde(rename = "fixed-address", default)]
+    pub fixed_address: String,
+    #[serde(default)]
+    pub options: BTreeMap<String, Value>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl Host {
+    fn from_block(name: &str, body: &[Statement]) -> Self {
+        let mut host = Host {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        for statement in body.iter().filter(|x| x.block.is_none()) {
+            match statement.words.as_slice() {
+                [hardware, _, mac] if hardware == "hardware" => {
+                    host.hardware_ethernet = mac.to_lowercase()
+                }
+                [keyword, ..] if keyword == "fixed-address" => {
+                    host.fixed_address = statement.value(1)
+                }
+                [keyword, name, ..] if keyword == "option" => {
+                    host.options
+                        .insert(name.clone(), Value::String(statement.value(2)));
+                }
+                [keyword, ..] => {
+                    host.extra
+                        .insert(keyword.clone(), Value::String(statement.value(1)));
+                }
+                [] => {}
+            }
+        }
+        host
+    }
+
+    pub fn matches_mac(&self, mac: &str) -> bool {
+        !self.hardware_ethernet.is_empty() && self.hardware_ethernet.eq_ignore_ascii_case(mac)
+    }
+
+    pub fn matches_lease(&self, lease: &Lease) -> bool {
+        self.matches_mac(&lease.hardware_ethernet) || self.fixed_address == lease.ip
+    }
+
+    pub fn in_network(&self, network: u32, mask: u32) -> bool {
+        scopes::ip_to_u32(&self.fixed_address)
+            .map(|x| x & mask == network)
+            .unwrap_or(false)
+    }
+
+    // The host declaration as it goes into dhcpd.conf.
+    pub fn stanza(&self) -> String {
+        let mut lines = vec![format!("host {} {{", dhcpd_conf::word(&self.name))];
+        if !self.hardware_ethernet.is_empty() {
+            lines.push(format!("  hardware ethernet {};", self.hardware_ethernet));
+        }
+        if !self.fixed_address.is_empty() {
+            lines.push(format!("  fixed-address {};", self.fixed_address));
+        }
+        for (name, value) in self.options.iter() {
+            lines.push(format!(
+                "  option {} {};",
+                name,
+                dhcpd_conf::format_value(name, value)
+            ));
+        }
+        for (name, value) in self.extra.iter() {
+            lines.push(format!(
+                "  {} {};",
+                name,
+                dhcpd_conf::format_value(name, value)
+            ));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+}
+
+pub fn normalize_mac(mac: &str) -> Option<String> {
+    let pairs: Vec<&str> = mac.split(&[':', '-'][..]).collect();
+    if pairs.len() == 6
+        && pairs
+            .iter()
+            .all(|x| x.len() == 2 && u8::from_str_radix(x, 16).is_ok())
+    {
+        Some(pairs.join(":").to_lowercase())
+    } else {
+        None
+    }
+}
+
+pub fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(x) => x.clone(),
+        Value::Array(x) => x.iter().map(value_text).collect::<Vec<String>>().join(","),
+        x => x.to_string(),
+    }
+}
+
+// Hosts can be declared at the top level or inside subnet, shared-network
+// and group blocks.
+fn collect_hosts(statements: &[Statement], hosts: &mut Vec<Host>) {
+    for statement in statements.iter() {
+        if let Some(body) = &statement.block {
+            if statement.keyword() == "host" {
+                hosts.push(Host::from_block(&statement.value(1), body));
+            } else {
+                collect_hosts(body, hosts);
+            }
+        }
+    }
+}
+
+pub fn parse_hosts(text: &str) -> Result<Vec<Host>, DhcpctlError> {
+    let mut hosts = Vec::new();
+    collect_hosts(&dhcpd_conf::parse(text)?, &mut hosts);
+    Ok(hosts)
+}
+
+pub async fn load_hosts(file: Option<&Path>) -> Result<Vec<Host>, DhcpctlError> {
+    match file {
+        Some(file) => parse_hosts(&fs::read_to_string(file)?),
+        None => reqwest_handler::run("/config/hosts").await,
+    }
+}
+
+// Older API versions have no hosts endpoint, so views that only show
+// reservations as extra information carry on without them.
+pub async fn fetch_reservations() -> Result<Vec<Host>, DhcpctlError> {
+    Ok(reqwest_handler::run_optional("/config/hosts")
+        .await?
+        .unwrap_or_default())
+}
+
+pub fn find_reservation<'a>(hosts: &'a [Host], lease: &Lease) -> Option<&'a Host> {
+    hosts
+        .iter()
+        .find(|x| x.matches_mac(&lease.hardware_ethernet))
+        .or_else(|| hosts.iter().find(|x| x.fixed_address == lease.ip))
+}
+
+fn table_format() -> format::TableFormat {
+    format::FormatBuilder::new()
+        .column_separator(' ')
+        .separator(
+            format::LinePosition::Title,
+            format::LineSeparator::new('-', ' ', ' ', ' '),
+        )
+        .padding(1, 1)
+        .build()
+}
+
+fn print_hosts(hosts: &[&Host]) {
+    if hosts.is_empty() {
+        println!("No hosts found");
+        return;
+    }
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!(b -> "Name", b -> "MAC Address", b -> "Fixed Address", b -> "Options"));
+    for host in hosts.iter() {
+        table.add_row(row!(
+            &host.name,
+            &host.hardware_ethernet,
+            &host.fixed_address,
+            host.options
+                .iter()
+                .map(|(name, value)| format!("{} {}", name, value_text(value)))
+                .collect::<Vec<String>>()
+                .join("\n"),
+        ));
+    }
+    table.printstd();
+}
+
+pub async fn list_hosts(cidr: Option<String>, file: Option<&Path>) -> Result<(), DhcpctlError> {
+    let network = match &cidr {
+        Some(x) => Some(scopes::parse_cidr(x).ok_or(DhcpctlError::NotValidCIDR)?),
+        None => None,
+    };
+    let payload = load_hosts(file).await?;
+    let hosts: Vec<&Host> = payload
+        .iter()
+        .filter(|x| match network {
+            Some((network, mask)) => x.in_network(network, mask),
+            None => true,
+        })
+        .collect();
+    print_hosts(&hosts);
+    Ok(())
+}
+
+pub async fn search_hosts(search_word: &str, file: Option<&Path>) -> Result<(), DhcpctlError> {
+    let payload = load_hosts(file).await?;
+    let word = search_word.to_lowercase();
+    let hosts: Vec<&Host> = payload
+        .iter()
+        .filter(|x| {
+            x.name.to_lowercase().contains(&word)
+                || x.hardware_ethernet.to_lowercase().contains(&word)
+                || x.fixed_address.contains(&word)
+        })
+        .collect();
+    print_hosts(&hosts);
+    Ok(())
+}
+
+fn print_field(label: &str, value: &str) {
+    println!("{} {}", format!("{}:", label).bold(), value);
+}
+
+pub fn print_host_detail(host: &Host) {
+    print_field("Name", &host.name);
+    print_field(
+        "MAC Address",
+        if host.hardware_ethernet.is_empty() {
+            "Not set"
+        } else {
+            &host.hardware_ethernet
+        },
+    );
+    print_field("Fixed Address", &host.fixed_address);
+    for (name, value) in host.options.iter() {
+        print_field(&format!("Option {}", name), &value_text(value));
+    }
+    leases::print_extra_fields(&host.extra);
+}
+
+pub async fn get_host(target: &str, file: Option<&Path>) -> Result<(), DhcpctlError> {
+    let payload = load_hosts(file).await?;
+    let mac = target.replace('-', ":");
+    let matched: Vec<&Host> = payload
+        .iter()
+        .filter(|x| {
+            x.name.eq_ignore_ascii_case(target) || x.matches_mac(&mac) || x.fixed_address == target
+        })
+        .collect();
+
+    if matched.is_empty() {
+        println!("No host found for {}", target);
+    }
+    for (index, host) in matched.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        print_host_detail(host);
+    }
+    Ok(())
+}
+
+// Everything that stops the reservation from working as intended. The IP has
+// to be in a known subnet but outside its dynamic ranges, and neither the IP
+// nor the MAC may be reserved or leased to another client already.
+fn check_new_host(host: &Host, scopes: &[Scope], leases: &[Lease], hosts: &[Host]) -> Vec<String> {
+    let mut problems = Vec::new();
+    match scopes.iter().find(|x| x.contains(&host.fixed_address)) {
+        None => problems.push(format!("{} is not in any scope", host.fixed_address)),
+        Some(scope) if scope.range_contains(&host.fixed_address) => problems.push(format!(
+            "{} is inside the dynamic range {} of {}",
+            host.fixed_address,
+            scope.range_label(),
+            scope.cidr()
+        )),
+        Some(_) => {}
+    }
+    for existing in hosts.iter() {
+        if existing.name.eq_ignore_ascii_case(&host.name) {
+            problems.push(format!("the name {} is already used", host.name));
+        }
+        if existing.fixed_address == host.fixed_address {
+            problems.push(format!(
+                "{} is already reserved for {}",
+                host.fixed_address, existing.name
+            ));
+        }
+        if existing.matches_mac(&host.hardware_ethernet) {
+            problems.push(format!(
+                "{} is already reserved as {}",
+                host.hardware_ethernet, existing.name
+            ));
+        }
+    }
+    for lease in leases.iter().filter(|x| {
+        x.binding_state == "active"
+            && x.ip == host.fixed_address
+            && !host.matches_mac(&x.hardware_ethernet)
+    }) {
+        problems.push(format!(
+            "{} is leased to {}",
+            lease.ip, lease.hardware_ethernet
+        ));
+    }
+    problems
+}
+
+pub async fn add_host(
+    name: &str,
+    mac: &str,
+    ip: &str,
+    yes: bool,
+    dry_run: bool,
+) -> Result<(), DhcpctlError> {
+    let hardware_ethernet = normalize_mac(mac)
+        .ok_or_else(|| DhcpctlError::InvalidHost(format!("{} is not a valid MAC address", mac)))?;
+    if scopes::ip_to_u32(ip).is_none() {
+        return Err(DhcpctlError::InvalidHost(format!(
+            "{} is not a valid IP address",
+            ip
+        )));
+    }
+    let host = Host {
+        name: name.to_string(),
+        hardware_ethernet,
+        fixed_address: ip.to_string(),
+        ..Default::default()
+    };
+
+    let (scopes, leases, hosts): (Vec<Scope>, Vec<Lease>, Vec<Host>) = tokio::try_join!(
+        reqwest_handler::run("/config/scopes"),
+        reqwest_handler::run("/leases/"),
+        reqwest_handler::run("/config/hosts")
+    )?;
+    let problems = check_new_host(&host, &scopes, &leases, &hosts);
+    if !problems.is_empty() {
+        return Err(DhcpctlError::InvalidHost(problems.join(", ")));
+    }
+
+    println!("{}", host.stanza());
+    if dry_run {
+        println!("\n{}", "Dry run, nothing was changed.".cyan());
+        return Ok(());
+    }
+    if !audit::confirm(&format!("Add host {}?", host.name), yes)? {
+        println!("Aborted");
+        return Ok(());
+    }
+    reqwest_handler::write(Method::POST, "/config/hosts", Some(&host)).await?;
+    println!("\nAdded host {}", host.name.green());
+    Ok(())
+}
+
+pub async fn remove_host(target: &str, yes: bool, dry_run: bool) -> Result<(), DhcpctlError> {
+    let payload: Vec<Host> = reqwest_handler::run("/config/hosts").await?;
+    let mac = normalize_mac(target).unwrap_or_default();
+    let matched: Vec<&Host> = payload
+        .iter()
+        .filter(|x| {
+            x.name.eq_ignore_ascii_case(target) || x.matches_mac(&mac) || x.fixed_address == target
+        })
+        .collect();
+
+    let host = match matched.as_slice() {
+        [host] => *host,
+        [] => {
+            return Err(DhcpctlError::InvalidHost(format!(
+                "no host found for {}",
+                target
+            )))
+        }
+        _ => {
+            return Err(DhcpctlError::InvalidHost(format!(
+                "{} matches {}, use the name",
+                target,
+                matched
+                    .iter()
+                    .map(|x| x.name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            )))
+        }
+    };
+
+    println!("{}", host.stanza());
+    if dry_run {
+        println!("\n{}", "Dry run, nothing was changed.".cyan());
+        return Ok(());
+    }
+    if !audit::confirm(&format!("Remove host {}?", host.name), yes)? {
+        println!("Aborted");
+        return Ok(());
+    }
+    reqwest_handler::write::<()>(
+        Method::DELETE,
+        &format!(
+            "/config/hosts/{}",
+            reqwest_handler::path_segment(&host.name)
+        ),
+        None,
+    )
+    .await?;
+    println!("\nRemoved host {}", host.name.green());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scopes() -> Vec<Scope> {
+        serde_json::from_value(json!([{
+            "ip": "10.0.1.0",
+            "subnet": "255.255.255.0",
+            "range": {"start": "10.0.1.100", "end": "10.0.1.199"},
+            "options": {"routers": "10.0.1.1"}
+        }]))
+        .unwrap()
+    }
+
+    fn leases() -> Vec<Lease> {
+        serde_json::from_value(json!([
+            {"ip": "10.0.1.30", "hardware-ethernet": "00:1a:2b:3c:4d:5e", "binding-state": "active"},
+            {"ip": "10.0.1.31", "hardware-ethernet": "00:1a:2b:3c:4d:5f", "binding-state": "free"}
+        ]))
+        .unwrap()
+    }
+
+    fn host(name: &str, mac: &str, ip: &str) -> Host {
+        Host {
+            name: name.to_string(),
+            hardware_ethernet: mac.to_string(),
+            fixed_address: ip.to_string(),
+            ..Host::default()
+        }
+    }
+
+    fn problems(new: Host) -> Vec<String> {
+        let hosts = vec![host("printer-1", "da:a1:19:00:11:22", "10.0.1.20")];
+        check_new_host(&new, &scopes(), &leases(), &hosts)
+    }
+
+    #[test]
+    fn accepts_a_valid_host() {
+        assert!(problems(host("nas", "52:54:00:aa:bb:cc", "10.0.1.40")).is_empty());
+        // Free leases and the client's own lease don't count.
+        assert!(problems(host("nas", "52:54:00:aa:bb:cc", "10.0.1.31")).is_empty());
+        assert!(problems(host("laptop", "00:1A:2B:3C:4D:5E", "10.0.1.30")).is_empty());
+    }
+
+    #[test]
+    fn rejects_an_ip_outside_every_scope() {
+        assert_eq!(
+            problems(host("nas", "52:54:00:aa:bb:cc", "10.0.2.40")),
+            vec!["10.0.2.40 is not in any scope"]
+        );
+    }
+
+    #[test]
+    fn rejects_an_ip_in_the_dynamic_range() {
+        assert_eq!(
+            problems(host("nas", "52:54:00:aa:bb:cc", "10.0.1.150")),
+            vec!["10.0.1.150 is inside the dynamic range 10.0.1.100 - 10.0.1.199 of 10.0.1.0/24"]
+        );
+    }
+
+    #[test]
+    fn rejects_a_leased_or_reserved_ip() {
+        assert_eq!(
+            problems(host("nas", "52:54:00:aa:bb:cc", "10.0.1.30")),
+            vec!["10.0.1.30 is leased to 00:1a:2b:3c:4d:5e"]
+        );
+        assert_eq!(
+            problems(host("nas", "52:54:00:aa:bb:cc", "10.0.1.20")),
+            vec!["10.0.1.20 is already reserved for printer-1"]
+        );
+    }
+
+    #[test]
+    fn rejects_a_reserved_mac_or_name() {
+        assert_eq!(
+            problems(host("nas", "DA:A1:19:00:11:22", "10.0.1.40")),
+            vec!["DA:A1:19:00:11:22 is already reserved as printer-1"]
+        );
+        assert_eq!(
+            problems(host("Printer-1", "52:54:00:aa:bb:cc", "10.0.1.40")),
+            vec!["the name Printer-1 is already used"]
+        );
+    }
+}
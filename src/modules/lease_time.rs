@@ -0,0 +1,262 @@
+use super::config;
+use super::error::DhcpctlError;
+use super::leases;
+use chrono::format::{Item, StrftimeItems};
+use chrono::prelude::*;
+use chrono_tz::Tz;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+static DISPLAY: OnceLock<(DisplayZone, bool)> = OnceLock::new();
+static DATE_FORMAT: OnceLock<String> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy)]
+pub enum DisplayZone {
+    Local,
+    Utc,
+    Named(Tz),
+}
+
+impl FromStr for DisplayZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(DisplayZone::Local),
+            "utc" => Ok(DisplayZone::Utc),
+            _ => s
+                .parse::<Tz>()
+                .map(DisplayZone::Named)
+                .map_err(|_| format!("Unknown time zone '{}', use local, utc or an IANA name like Europe/Copenhagen.", s)),
+        }
+    }
+}
+
+pub fn select_display(time_zone: DisplayZone, relative: bool) {
+    let _ = DISPLAY.set((time_zone, relative));
+}
+
+fn display() -> (DisplayZone, bool) {
+    DISPLAY
+        .get()
+        .copied()
+        .unwrap_or((DisplayZone::Local, false))
+}
+
+pub fn check_date_format(format: &str) -> Result<(), DhcpctlError> {
+    if StrftimeItems::new(format).any(|x| matches!(x, Item::Error)) {
+        return Err(DhcpctlError::InvalidDateFormat(format.to_string()));
+    }
+    Ok(())
+}
+
+// Read from the config file on first use. An invalid format would make chrono
+// panic while printing, so it falls back to the default.
+fn date_format() -> &'static str {
+    DATE_FORMAT.get_or_init(|| {
+        config::load_config()
+            .ok()
+            .and_then(|x| x.date_format)
+            .filter(|x| check_date_format(x).is_ok())
+            .unwrap_or_else(|| DEFAULT_DATE_FORMAT.to_string())
+    })
+}
+
+fn format_relative(delta: chrono::Duration) -> String {
+    if delta < chrono::Duration::zero() {
+        format!("{} ago", leases::format_duration(delta))
+    } else {
+        format!("in {}", leases::format_duration(delta))
+    }
+}
+
+pub fn format_time(time: DateTime<Utc>) -> String {
+    let (time_zone, relative) = display();
+    if relative {
+        return format_relative(time - Utc::now());
+    }
+
+    let format = date_format();
+    match time_zone {
+        DisplayZone::Local => time.with_timezone(&Local).format(format).to_string(),
+        DisplayZone::Utc => time.format(format).to_string(),
+        DisplayZone::Named(tz) => time.with_timezone(&tz).format(format).to_string(),
+    }
+}
+
+// Lease times as the API passes them on from dhcpd. Besides RFC 3339 they can
+// be `never` for infinite leases, `epoch 1631779200` or a bare epoch when
+// dhcpd runs with `db-time-format local`, or the default dhcpd.leases format
+// `w yyyy/mm/dd hh:mm:ss` in UTC, where `w` is the day of the week.
+//
+// The variant order makes unparsable times sort first and infinite leases last.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LeaseTime {
+    Invalid(String),
+    At(DateTime<Utc>),
+    Never,
+}
+
+fn parse_isc(value: &str) -> Option<DateTime<Utc>> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let date_time = match parts.as_slice() {
+        [weekday, date, time] if weekday.len() == 1 => format!("{} {}", date, time),
+        [date, time] => format!("{} {}", date, time),
+        _ => return None,
+    };
+    ["%Y/%m/%d %H:%M:%S", "%Y-%m-%d %H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&date_time, format).ok())
+        .map(|x| Utc.from_utc_datetime(&x))
+}
+
+fn parse_epoch(value: &str) -> Option<DateTime<Utc>> {
+    let seconds = value
+        .trim_start_matches("epoch")
+        .trim()
+        .parse::<i64>()
+        .ok()?;
+    Utc.timestamp_opt(seconds, 0).single()
+}
+
+impl LeaseTime {
+    pub fn parse(value: &str) -> Self {
+        // dhcpd.leases values may still carry the trailing `; # comment`.
+        let trimmed = value.split(';').next().unwrap_or_default().trim();
+        if trimmed.eq_ignore_ascii_case("never") {
+            return LeaseTime::Never;
+        }
+        DateTime::parse_from_rfc3339(trimmed)
+            .ok()
+            .map(|x| x.with_timezone(&Utc))
+            .or_else(|| parse_epoch(trimmed))
+            .or_else(|| parse_isc(trimmed))
+            .map(LeaseTime::At)
+            .unwrap_or_else(|| LeaseTime::Invalid(value.to_string()))
+    }
+}
+
+impl fmt::Display for LeaseTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LeaseTime::Invalid(raw) => write!(f, "{}", raw),
+            LeaseTime::At(x) => write!(f, "{}", format_time(*x)),
+            LeaseTime::Never => write!(f, "never"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> LeaseTime {
+        LeaseTime::At(
+            DateTime::parse_from_rfc3339(value)
+                .unwrap()
+                .with_timezone(&Utc),
+        )
+    }
+
+    #[test]
+    fn parses_rfc3339() {
+        assert_eq!(
+            LeaseTime::parse("2021-09-16T10:00:00+02:00"),
+            at("2021-09-16T08:00:00Z")
+        );
+    }
+
+    #[test]
+    fn parses_epoch() {
+        assert_eq!(
+            LeaseTime::parse("epoch 1631779200"),
+            at("2021-09-16T08:00:00Z")
+        );
+        assert_eq!(LeaseTime::parse("1631779200"), at("2021-09-16T08:00:00Z"));
+    }
+
+    #[test]
+    fn parses_never() {
+        assert_eq!(LeaseTime::parse("never"), LeaseTime::Never);
+        assert_eq!(LeaseTime::parse("NEVER;"), LeaseTime::Never);
+    }
+
+    #[test]
+    fn parses_isc_format() {
+        assert_eq!(
+            LeaseTime::parse("4 2021/09/16 08:00:00"),
+            at("2021-09-16T08:00:00Z")
+        );
+        assert_eq!(
+            LeaseTime::parse("2021-09-16 08:00:00"),
+            at("2021-09-16T08:00:00Z")
+        );
+    }
+
+    #[test]
+    fn ignores_trailing_semicolon_and_comment() {
+        assert_eq!(
+            LeaseTime::parse("4 2021/09/16 08:00:00; # renewed"),
+            at("2021-09-16T08:00:00Z")
+        );
+        assert_eq!(
+            LeaseTime::parse("epoch 1631779200; # Thu Sep 16 08:00:00 2021"),
+            at("2021-09-16T08:00:00Z")
+        );
+    }
+
+    #[test]
+    fn invalid_input_is_kept() {
+        for value in ["", "soon", "2021/13/40 25:00:00", "4 2021/09/16"].iter() {
+            assert_eq!(
+                LeaseTime::parse(value),
+                LeaseTime::Invalid(value.to_string())
+            );
+        }
+        assert_eq!(LeaseTime::parse("soon").to_string(), "soon");
+    }
+
+    #[test]
+    fn invalid_sorts_first_and_never_last() {
+        let mut times = vec![
+            LeaseTime::Never,
+            at("2021-09-16T08:00:00Z"),
+            LeaseTime::parse("soon"),
+        ];
+        times.sort();
+        assert_eq!(
+            times,
+            vec![
+                LeaseTime::parse("soon"),
+                at("2021-09-16T08:00:00Z"),
+                LeaseTime::Never
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_times() {
+        let minutes = |x| chrono::Duration::minutes(x);
+        assert_eq!(format_relative(minutes(133)), "in 2h13m");
+        assert_eq!(format_relative(-minutes(3 * 24 * 60 + 5)), "3d0h ago");
+        assert_eq!(format_relative(minutes(7)), "in 7m");
+        assert_eq!(format_relative(chrono::Duration::seconds(-42)), "42s ago");
+    }
+
+    #[test]
+    fn time_zones() {
+        assert!(matches!("UTC".parse::<DisplayZone>(), Ok(DisplayZone::Utc)));
+        assert!(matches!(
+            "local".parse::<DisplayZone>(),
+            Ok(DisplayZone::Local)
+        ));
+        assert!(matches!(
+            "Europe/Copenhagen".parse::<DisplayZone>(),
+            Ok(DisplayZone::Named(_))
+        ));
+        assert!("Mars/Olympus".parse::<DisplayZone>().is_err());
+    }
+}
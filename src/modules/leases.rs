@@ -1,33 +1,51 @@
+use super::audit;
 use super::error::DhcpctlError;
+use super::fingerprint::{Device, Fingerprinter};
+use super::hosts::{self, Host};
+use super::lease_time::LeaseTime;
 use super::reqwest_handler;
+use super::uid;
 use chrono::prelude::*;
 use cidr_utils::cidr::IpCidr;
 use colored::Colorize;
 use futures;
 use prettytable::{cell, format, row, Attr, Cell, Row, Table};
 use reqwest;
+use reqwest::Method;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::str::FromStr;
 
+// Everything but the IP and MAC defaults, so a single lease missing a field
+// doesn't fail the whole response. Fields the API adds later are kept in
+// `extra` and shown in the detail view and JSON output.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Lease {
-    #[serde(rename = "binding-state")]
-    binding_state: String,
+    #[serde(rename = "binding-state", default)]
+    pub binding_state: String,
     #[serde(rename = "client-hostname")]
-    client_hostname: Option<String>,
-    cltt: String,
-    ends: String,
+    pub client_hostname: Option<String>,
+    #[serde(default)]
+    pub cltt: String,
+    #[serde(default)]
+    pub ends: String,
     #[serde(rename = "hardware-ethernet")]
-    hardware_ethernet: String,
-    ip: String,
-    #[serde(rename = "next-binding-state")]
-    next_binding_state: String,
-    #[serde(rename = "rewind-binding-state")]
-    rewind_binding_state: String,
+    pub hardware_ethernet: String,
+    pub ip: String,
+    #[serde(rename = "next-binding-state", default)]
+    pub next_binding_state: String,
+    #[serde(rename = "rewind-binding-state", default)]
+    pub rewind_binding_state: String,
     #[serde(rename = "set-vendor-class-identifier")]
-    set_vendor_class_identifier: Option<String>,
-    starts: String,
-    uid: Option<String>,
+    pub set_vendor_class_identifier: Option<String>,
+    #[serde(default)]
+    pub starts: String,
+    pub uid: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -42,22 +60,294 @@ pub struct MacVendorLookup {
     pub error: Option<String>,
 }
 
-pub async fn get_leases(cidr: String, mac_lookup: bool) -> Result<(), DhcpctlError> {
+const EXPIRING_SOON: i64 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("Unknown output format '{}'.", s)),
+        }
+    }
+}
+
+pub struct ListOptions {
+    pub mac_lookup: bool,
+    pub client_id: bool,
+    pub fingerprint: bool,
+    pub device: Option<String>,
+    pub output: OutputFormat,
+}
+
+#[derive(Serialize)]
+struct LeaseOutput<'a> {
+    #[serde(flatten)]
+    lease: &'a Lease,
+    #[serde(rename = "uid-decoded", skip_serializing_if = "Option::is_none")]
+    uid_decoded: Option<uid::ClientId>,
+    #[serde(rename = "mac-vendor", skip_serializing_if = "Option::is_none")]
+    mac_vendor: Option<&'a String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<&'a Device>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reservation: Option<&'a String>,
+}
+
+pub async fn get_leases(cidr: String, options: &ListOptions) -> Result<(), DhcpctlError> {
     if *&cidr.len() != 0 && !IpCidr::is_ipv4_cidr(&cidr) {
         return Err(DhcpctlError::NotValidCIDR);
     }
     let payload: Vec<Lease> = reqwest_handler::run(format!("/leases/{}", cidr).as_str()).await?;
-    print_leases(payload, mac_lookup).await?;
+    print_leases(payload, options).await?;
     Ok(())
 }
 
-pub async fn search_leases(search_word: String, mac_lookup: bool) -> Result<(), DhcpctlError> {
+pub async fn search_leases(search_word: String, options: &ListOptions) -> Result<(), DhcpctlError> {
     let payload: Vec<Lease> =
         reqwest_handler::run(format!("/leases/search/{}", search_word).as_str()).await?;
-    print_leases(payload, mac_lookup).await?;
+    print_leases(payload, options).await?;
     Ok(())
 }
 
+fn print_field(label: &str, value: &str) {
+    println!("{} {}", format!("{}:", label).bold(), value);
+}
+
+fn print_lease_detail(lease: &Lease) {
+    print_field("IP", &lease.ip);
+    print_field("MAC Address", &lease.hardware_ethernet);
+    print_field("MAC Type", mac_type(&lease.hardware_ethernet));
+    print_field(
+        "Hostname",
+        lease.client_hostname.as_deref().unwrap_or("Not set"),
+    );
+    print_field("Binding State", &lease.binding_state);
+    print_field("Next Binding State", &lease.next_binding_state);
+    print_field("Rewind Binding State", &lease.rewind_binding_state);
+    print_field("Starts", &LeaseTime::parse(&lease.starts).to_string());
+    print_field("Ends", &LeaseTime::parse(&lease.ends).to_string());
+    if let Some(remaining) = time_remaining(&lease.ends) {
+        print_field("Remaining", &remaining);
+    }
+    print_field(
+        "Last Transaction",
+        &LeaseTime::parse(&lease.cltt).to_string(),
+    );
+    print_field(
+        "Vendor Identifier",
+        lease
+            .set_vendor_class_identifier
+            .as_deref()
+            .unwrap_or("Not set"),
+    );
+    match &lease.uid {
+        Some(raw) => {
+            let client_id = uid::decode(raw, &lease.hardware_ethernet);
+            print_field("Client Identifier", raw);
+            print_field("Decoded Identifier", &client_id.describe());
+            if client_id.mac_mismatch {
+                print_field(
+                    "Warning",
+                    &"client identifier MAC differs from the lease MAC"
+                        .red()
+                        .to_string(),
+                );
+            }
+        }
+        None => print_field("Client Identifier", "Not set"),
+    }
+    print_extra_fields(&lease.extra);
+}
+
+pub fn print_extra_fields(extra: &BTreeMap<String, Value>) {
+    for (name, value) in extra.iter() {
+        match value {
+            Value::String(x) => print_field(name, x),
+            x => print_field(name, &x.to_string()),
+        }
+    }
+}
+
+pub async fn get_lease(target: &str) -> Result<(), DhcpctlError> {
+    let payload: Vec<Lease> = reqwest_handler::run("/leases/").await?;
+    let mac = target.replace('-', ":").to_lowercase();
+    let is_ip = target.parse::<Ipv4Addr>().is_ok();
+
+    let mut matched: Vec<&Lease> = payload
+        .iter()
+        .filter(|x| {
+            if is_ip {
+                x.ip == target
+            } else {
+                x.hardware_ethernet.to_lowercase() == mac
+            }
+        })
+        .collect();
+    matched.sort_by_key(|x| Reverse(LeaseTime::parse(&x.starts)));
+
+    // Prefer the newest active lease, the rest are shown as history.
+    let lease = match matched
+        .iter()
+        .find(|x| x.binding_state == "active")
+        .or_else(|| matched.first())
+    {
+        Some(x) => *x,
+        None => {
+            println!("No lease found for {}", target);
+            return Ok(());
+        }
+    };
+    print_lease_detail(lease);
+
+    let mut previous: Vec<&Lease> = payload
+        .iter()
+        .filter(|x| {
+            x.hardware_ethernet
+                .eq_ignore_ascii_case(&lease.hardware_ethernet)
+                && !std::ptr::eq(*x, lease)
+        })
+        .collect();
+    previous.sort_by_key(|x| Reverse(LeaseTime::parse(&x.starts)));
+
+    if !previous.is_empty() {
+        println!("\n{}", "Previous Leases".underline().bold());
+        let mut table = Table::new();
+        table.set_format(table_format());
+        table.set_titles(
+            row!(b -> "IP", b -> "Status", b -> "Hostname", b -> "Starts", b -> "Ends"),
+        );
+        for lease in previous.iter() {
+            table.add_row(row!(
+                &lease.ip,
+                &lease.binding_state,
+                lease.client_hostname.clone().unwrap_or_default(),
+                LeaseTime::parse(&lease.starts),
+                LeaseTime::parse(&lease.ends),
+            ));
+        }
+        table.printstd();
+    }
+    Ok(())
+}
+
+fn lease_state(lease: Option<&Lease>) -> String {
+    match lease {
+        Some(x) => format!(
+            "{} {} {} (ends {})",
+            x.ip,
+            x.hardware_ethernet,
+            x.binding_state,
+            LeaseTime::parse(&x.ends)
+        ),
+        None => "no lease".to_string(),
+    }
+}
+
+// Releases the active leases for an IP or MAC, so a re-imaged machine can get
+// a fresh lease without stopping dhcpd.
+pub async fn release_lease(target: &str, yes: bool, dry_run: bool) -> Result<(), DhcpctlError> {
+    let is_ip = target.parse::<Ipv4Addr>().is_ok();
+    let mac = hosts::normalize_mac(target);
+    if !is_ip && mac.is_none() {
+        return Err(DhcpctlError::NotValidTarget(target.to_string()));
+    }
+
+    let payload: Vec<Lease> = reqwest_handler::run("/leases/").await?;
+    let matched: Vec<&Lease> = payload
+        .iter()
+        .filter(|x| x.binding_state == "active")
+        .filter(|x| match &mac {
+            Some(mac) => x.hardware_ethernet.eq_ignore_ascii_case(mac),
+            None => x.ip == target,
+        })
+        .collect();
+
+    if matched.is_empty() {
+        println!("No active lease found for {}", target);
+        return Ok(());
+    }
+    for lease in matched.iter() {
+        print_field("Before", &lease_state(Some(lease)));
+    }
+    if dry_run {
+        println!("\n{}", "Dry run, nothing was changed.".cyan());
+        return Ok(());
+    }
+    if !audit::confirm(&format!("Release {} lease(s)?", matched.len()), yes)? {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    for lease in matched.iter() {
+        reqwest_handler::write::<()>(Method::DELETE, &format!("/leases/{}", lease.ip), None)
+            .await?;
+    }
+
+    let after: Vec<Lease> = reqwest_handler::run("/leases/").await?;
+    for lease in matched.iter() {
+        let current = after
+            .iter()
+            .filter(|x| x.ip == lease.ip)
+            .max_by_key(|x| LeaseTime::parse(&x.starts));
+        print_field("After", &lease_state(current));
+    }
+    Ok(())
+}
+
+// The second least significant bit of the first octet marks a locally
+// administered address, which is what randomized MACs use.
+pub fn is_locally_administered(mac: &str) -> bool {
+    mac.len() > 1 && ["2", "6", "a", "e"].contains(&&mac[1..2].to_lowercase()[..])
+}
+
+pub fn mac_type(mac: &str) -> &'static str {
+    let first = u8::from_str_radix(mac.get(0..2).unwrap_or(""), 16).unwrap_or(0);
+    if first & 0x01 == 1 {
+        "Multicast"
+    } else if is_locally_administered(mac) {
+        "Locally administered (randomized)"
+    } else {
+        "Universally administered"
+    }
+}
+
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let seconds = duration.num_seconds().abs();
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    if days > 0 {
+        format!("{}d{}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h{}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+pub fn time_remaining(ends: &str) -> Option<String> {
+    let ends = match LeaseTime::parse(ends) {
+        LeaseTime::At(x) => x,
+        LeaseTime::Never => return Some("never expires".to_string()),
+        LeaseTime::Invalid(_) => return None,
+    };
+    let remaining = ends - Utc::now();
+    if remaining < chrono::Duration::zero() {
+        Some(format!("expired {} ago", format_duration(remaining)))
+    } else {
+        Some(format!("{} left", format_duration(remaining)))
+    }
+}
+
 fn table_format() -> format::TableFormat {
     format::FormatBuilder::new()
         .column_separator(' ')
@@ -69,7 +359,133 @@ fn table_format() -> format::TableFormat {
         .build()
 }
 
-async fn print_leases(leases: Vec<Lease>, mac_lookup: bool) -> Result<(), DhcpctlError> {
+fn print_json(
+    leases: &[Lease],
+    vendors: &HashMap<String, String>,
+    devices: &[Device],
+    reservations: &[Host],
+) -> Result<(), DhcpctlError> {
+    let output: Vec<LeaseOutput> = leases
+        .iter()
+        .enumerate()
+        .map(|(index, lease)| LeaseOutput {
+            lease,
+            uid_decoded: lease
+                .uid
+                .as_ref()
+                .map(|x| uid::decode(x, &lease.hardware_ethernet)),
+            mac_vendor: lease
+                .hardware_ethernet
+                .get(0..8)
+                .and_then(|x| vendors.get(&x.to_lowercase())),
+            device: devices.get(index),
+            reservation: hosts::find_reservation(reservations, lease).map(|x| &x.name),
+        })
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+fn client_id_cell(lease: &Lease) -> Cell {
+    match &lease.uid {
+        Some(raw) => {
+            let client_id = uid::decode(raw, &lease.hardware_ethernet);
+            if client_id.mac_mismatch {
+                Cell::new(&format!("{} (MAC mismatch)", client_id.describe())).style_spec("Fr")
+            } else {
+                Cell::new(&client_id.describe())
+            }
+        }
+        None => Cell::new(""),
+    }
+}
+
+// A reserved MAC holding another IP than its fixed address, or a reserved IP
+// leased to another MAC, is red.
+fn reservation_cell(host: Option<&Host>, lease: &Lease) -> Cell {
+    match host {
+        Some(host) if host.fixed_address != lease.ip => {
+            Cell::new(&format!("{} (reserved {})", host.name, host.fixed_address)).style_spec("Fr")
+        }
+        Some(host)
+            if !host.hardware_ethernet.is_empty()
+                && !host.matches_mac(&lease.hardware_ethernet) =>
+        {
+            Cell::new(&format!(
+                "{} (reserved for {})",
+                host.name, host.hardware_ethernet
+            ))
+            .style_spec("Fr")
+        }
+        Some(host) => Cell::new(&host.name),
+        None => Cell::new(""),
+    }
+}
+
+// Classifies the leases and drops the ones not matching the --device filter.
+// The returned devices are in the same order as the leases.
+fn classify_leases(
+    leases: Vec<Lease>,
+    device_filter: &Option<String>,
+) -> Result<(Vec<Lease>, Vec<Device>), DhcpctlError> {
+    let fingerprinter = Fingerprinter::load()?;
+    let filter = device_filter.as_ref().map(|x| x.to_lowercase());
+    Ok(leases
+        .into_iter()
+        .map(|lease| {
+            let device = fingerprinter.classify(&lease);
+            (lease, device)
+        })
+        .filter(|(_, device)| match &filter {
+            Some(filter) => device.to_string().to_lowercase().contains(filter),
+            None => true,
+        })
+        .unzip())
+}
+
+// Active leases running out within EXPIRING_SOON are yellow, active leases
+// past their end time are red.
+fn ends_cell(lease: &Lease) -> Cell {
+    let ends = LeaseTime::parse(&lease.ends);
+    let cell = Cell::new(&ends.to_string());
+    match ends {
+        LeaseTime::At(x) if lease.binding_state == "active" => {
+            let remaining = x - Utc::now();
+            if remaining < chrono::Duration::zero() {
+                cell.style_spec("Fr")
+            } else if remaining < chrono::Duration::minutes(EXPIRING_SOON) {
+                cell.style_spec("Fy")
+            } else {
+                cell
+            }
+        }
+        _ => cell,
+    }
+}
+
+async fn print_leases(mut leases: Vec<Lease>, options: &ListOptions) -> Result<(), DhcpctlError> {
+    let mac_lookup = options.mac_lookup;
+    let fingerprint = options.fingerprint || options.device.is_some();
+    let mut devices: Vec<Device> = Vec::new();
+    if fingerprint {
+        let classified = classify_leases(leases, &options.device)?;
+        leases = classified.0;
+        devices = classified.1;
+    }
+    let reservations = hosts::fetch_reservations().await?;
+
+    if options.output == OutputFormat::Json {
+        let mut vendors = HashMap::new();
+        if mac_lookup {
+            let macs: HashSet<&str> = leases
+                .iter()
+                .filter_map(|x| x.hardware_ethernet.get(0..8))
+                .collect();
+            vendors = get_vendors(&macs).await?;
+        }
+        return print_json(&leases, &vendors, &devices, &reservations);
+    }
+
     if leases.len() > 0 {
         let mut vendor_lookup_tabel: HashMap<String, String> = HashMap::new();
 
@@ -82,40 +498,69 @@ async fn print_leases(leases: Vec<Lease>, mac_lookup: bool) -> Result<(), Dhcpct
                 "\n{}\n",
                 "The vendor lookup data is from https://macvendors.co. Too many requests could result in API limitations.".cyan()
             );
-            let macs: HashSet<&str> = leases.iter().map(|x| &x.hardware_ethernet[0..8]).collect();
+            let macs: HashSet<&str> = leases
+                .iter()
+                .filter_map(|x| x.hardware_ethernet.get(0..8))
+                .collect();
             vendor_lookup_tabel = get_vendors(&macs).await?;
             row.insert_cell(0, Cell::new("Mac Vendor").with_style(Attr::Bold));
         }
 
+        if fingerprint {
+            row.add_cell(Cell::new("Device").with_style(Attr::Bold));
+        }
+        if options.client_id {
+            row.add_cell(Cell::new("Client Identifier").with_style(Attr::Bold));
+        }
+        let reserved = leases
+            .iter()
+            .any(|x| hosts::find_reservation(&reservations, x).is_some());
+        if reserved {
+            row.add_cell(Cell::new("Reservation").with_style(Attr::Bold));
+        }
+
         table.set_titles(row);
 
-        for lease in leases.iter() {
+        for (index, lease) in leases.iter().enumerate() {
             let hostname = lease.client_hostname.clone().unwrap_or_default();
-            let ends = DateTime::parse_from_rfc3339(&lease.ends)?.format("%Y-%m-%d %H:%M:%S");
-            let starts = DateTime::parse_from_rfc3339(&lease.starts)?.format("%Y-%m-%d %H:%M:%S");
             let mut row: Row = row!(
                 &lease.hardware_ethernet,
                 &lease.binding_state,
                 &lease.ip,
                 &hostname,
-                &starts,
-                &ends,
+                LeaseTime::parse(&lease.starts),
                 lease
                     .set_vendor_class_identifier
                     .clone()
                     .unwrap_or_default(),
             );
 
+            row.insert_cell(5, ends_cell(lease));
+
             if mac_lookup {
                 row.insert_cell(
                     0,
                     Cell::new(
-                        vendor_lookup_tabel
-                            .get(&lease.hardware_ethernet[0..8])
+                        lease
+                            .hardware_ethernet
+                            .get(0..8)
+                            .and_then(|x| vendor_lookup_tabel.get(x))
                             .unwrap_or(&"".to_string()),
                     ),
                 )
             }
+            if let Some(device) = devices.get(index) {
+                row.add_cell(Cell::new(&device.to_string()));
+            }
+            if options.client_id {
+                row.add_cell(client_id_cell(lease));
+            }
+            if reserved {
+                row.add_cell(reservation_cell(
+                    hosts::find_reservation(&reservations, lease),
+                    lease,
+                ));
+            }
 
             table.add_row(row);
         }
@@ -126,13 +571,13 @@ async fn print_leases(leases: Vec<Lease>, mac_lookup: bool) -> Result<(), Dhcpct
     Ok(())
 }
 
-async fn get_vendors(macs: &HashSet<&str>) -> Result<HashMap<String, String>, DhcpctlError> {
+pub async fn get_vendors(macs: &HashSet<&str>) -> Result<HashMap<String, String>, DhcpctlError> {
     let mut mac_hashmap: HashMap<String, String> = HashMap::new();
     let client = reqwest::Client::new();
     let mut vendor_requests = Vec::new();
 
     for mac in macs {
-        if ["2", "6", "a", "e"].contains(&&mac[1..2]) {
+        if is_locally_administered(mac) {
             mac_hashmap.insert(mac.to_string(), "::randomized::".to_string());
         } else {
             vendor_requests.push(
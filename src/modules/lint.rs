@@ -0,0 +1,616 @@
+use super::error::DhcpctlError;
+use super::globals::Globals;
+use super::reqwest_handler;
+use super::scopes::{ip_to_u32, Range, Scope};
+use colored::Colorize;
+use prettytable::{cell, format, row, Cell, Row, Table};
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => Err(format!("Unknown severity '{}'.", s)),
+        }
+    }
+}
+
+pub struct Finding {
+    pub severity: Severity,
+    pub subject: String,
+    pub message: String,
+}
+
+struct Findings(Vec<Finding>);
+
+impl Findings {
+    fn add(&mut self, severity: Severity, subject: &str, message: String) {
+        self.0.push(Finding {
+            severity,
+            subject: subject.to_string(),
+            message,
+        });
+    }
+}
+
+fn lease_time(value: &Option<String>) -> Option<u64> {
+    value.as_ref().and_then(|x| x.trim().parse::<u64>().ok())
+}
+
+// Only the values set on `subject` itself are checked. The inherited ones are
+// reported where they are set and only fill in the other side of the
+// default-lease-time and max-lease-time comparison.
+fn check_lease_times(
+    findings: &mut Findings,
+    subject: &str,
+    (default, max): (&Option<String>, &Option<String>),
+    (inherited_default, inherited_max): (&Option<String>, &Option<String>),
+) {
+    if default.is_none() && max.is_none() {
+        return;
+    }
+    for (name, value) in [("default-lease-time", default), ("max-lease-time", max)].iter() {
+        if let Some(raw) = value {
+            if lease_time(value).is_none() {
+                findings.add(
+                    Severity::Error,
+                    subject,
+                    format!("{} '{}' is not a number of seconds", name, raw),
+                );
+            }
+        }
+    }
+
+    let effective_default = lease_time(default).or_else(|| lease_time(inherited_default));
+    let effective_max = lease_time(max).or_else(|| lease_time(inherited_max));
+    if let (Some(default), Some(max)) = (effective_default, effective_max) {
+        if default > max {
+            findings.add(
+                Severity::Error,
+                subject,
+                format!(
+                    "default-lease-time {} is greater than max-lease-time {}",
+                    default, max
+                ),
+            );
+        }
+    }
+    if let Some(default) = lease_time(default) {
+        if default < 60 {
+            findings.add(
+                Severity::Warning,
+                subject,
+                format!("default-lease-time {} is very short", default),
+            );
+        }
+    }
+}
+
+fn check_globals(findings: &mut Findings, globals: &Globals) {
+    check_lease_times(
+        findings,
+        "globals",
+        (&globals.default_lease_time, &globals.max_lease_time),
+        (&None, &None),
+    );
+    if globals.authoritative != Some(true) {
+        findings.add(
+            Severity::Info,
+            "globals",
+            "server is not authoritative".to_string(),
+        );
+    }
+}
+
+fn check_range(findings: &mut Findings, subject: &str, range: &Range, network: u32, mask: u32) {
+    let broadcast = network | !mask;
+    let (start, end) = match range.bounds() {
+        Some(x) => x,
+        None => {
+            findings.add(
+                Severity::Error,
+                subject,
+                format!("range {} is not valid", range),
+            );
+            return;
+        }
+    };
+
+    if start > end {
+        findings.add(
+            Severity::Error,
+            subject,
+            format!(
+                "range start {} is after range end {}",
+                range.start, range.end
+            ),
+        );
+    }
+    for (name, ip) in [("start", start), ("end", end)].iter() {
+        if ip & mask != network {
+            findings.add(
+                Severity::Error,
+                subject,
+                format!(
+                    "range {} {} is outside the subnet",
+                    name,
+                    Ipv4Addr::from(*ip)
+                ),
+            );
+        }
+    }
+    if (start..=end).contains(&network) || (start..=end).contains(&broadcast) {
+        findings.add(
+            Severity::Error,
+            subject,
+            format!("range {} includes the network or broadcast address", range),
+        );
+    }
+}
+
+fn check_scope(findings: &mut Findings, scope: &Scope, globals: &Globals) {
+    let subject = scope.cidr();
+    let (network, mask) = match scope.network() {
+        Some(x) => x,
+        None => {
+            findings.add(
+                Severity::Error,
+                &subject,
+                format!("'{}/{}' is not a valid subnet", scope.ip, scope.subnet),
+            );
+            return;
+        }
+    };
+    let broadcast = network | !mask;
+
+    if ip_to_u32(&scope.ip) != Some(network) {
+        findings.add(
+            Severity::Warning,
+            &subject,
+            format!(
+                "{} is not the network address, expected {}",
+                scope.ip,
+                Ipv4Addr::from(network)
+            ),
+        );
+    }
+    if mask.leading_ones() != mask.count_ones() {
+        findings.add(
+            Severity::Error,
+            &subject,
+            format!("subnet mask {} is not contiguous", scope.subnet),
+        );
+    }
+
+    let ranges = scope.all_ranges();
+    if ranges.is_empty() {
+        findings.add(
+            Severity::Info,
+            &subject,
+            "no dynamic range, only reservations are served".to_string(),
+        );
+    }
+    for range in ranges.iter() {
+        check_range(findings, &subject, range, network, mask);
+    }
+    let intervals: Vec<(u32, u32)> = ranges
+        .iter()
+        .filter_map(|x| x.bounds())
+        .filter(|(start, end)| start <= end)
+        .collect();
+    for (index, a) in intervals.iter().enumerate() {
+        for b in intervals.iter().skip(index + 1) {
+            if a.0 <= b.1 && b.0 <= a.1 {
+                findings.add(
+                    Severity::Error,
+                    &subject,
+                    format!(
+                        "ranges {} - {} and {} - {} overlap",
+                        Ipv4Addr::from(a.0),
+                        Ipv4Addr::from(a.1),
+                        Ipv4Addr::from(b.0),
+                        Ipv4Addr::from(b.1)
+                    ),
+                );
+            }
+        }
+    }
+
+    // dhcpd derives the broadcast address and subnet mask options from the
+    // subnet declaration when they are left out.
+    match ip_to_u32(&scope.options.broadcast_address) {
+        _ if scope.options.broadcast_address.is_empty() => {}
+        Some(x) if x == broadcast => {}
+        _ => findings.add(
+            Severity::Error,
+            &subject,
+            format!(
+                "broadcast-address {} does not match the mask, expected {}",
+                scope.options.broadcast_address,
+                Ipv4Addr::from(broadcast)
+            ),
+        ),
+    }
+    if !scope.options.subnet_mask.is_empty() && scope.options.subnet_mask != scope.subnet {
+        findings.add(
+            Severity::Warning,
+            &subject,
+            format!(
+                "subnet-mask option {} differs from the subnet netmask {}",
+                scope.options.subnet_mask, scope.subnet
+            ),
+        );
+    }
+
+    let routers: Vec<&str> = scope
+        .options
+        .routers
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .collect();
+    if routers.is_empty() {
+        findings.add(Severity::Warning, &subject, "no routers set".to_string());
+    }
+    for router in routers {
+        match ip_to_u32(router) {
+            None => findings.add(
+                Severity::Error,
+                &subject,
+                format!("router {} is not a valid IP", router),
+            ),
+            Some(x) if x & mask != network => findings.add(
+                Severity::Error,
+                &subject,
+                format!("router {} is outside the subnet", router),
+            ),
+            Some(_) if scope.range_contains(router) => findings.add(
+                Severity::Error,
+                &subject,
+                format!("router {} is inside the dynamic range", router),
+            ),
+            Some(_) => {}
+        }
+    }
+
+    let global_dns = globals
+        .options
+        .as_ref()
+        .and_then(|x| x.domain_name_servers.as_ref());
+    match scope.options.domain_name_servers.as_ref().or(global_dns) {
+        Some(servers) if !servers.is_empty() => {}
+        _ => findings.add(
+            Severity::Warning,
+            &subject,
+            "no domain-name-servers in the scope or globals".to_string(),
+        ),
+    }
+
+    // Scope lease times fall back to the globals one by one.
+    check_lease_times(
+        findings,
+        &subject,
+        (&scope.default_lease_time, &scope.max_lease_time),
+        (&globals.default_lease_time, &globals.max_lease_time),
+    );
+}
+
+fn check_overlaps(findings: &mut Findings, scopes: &[Scope]) {
+    for (index, scope) in scopes.iter().enumerate() {
+        for other in scopes.iter().skip(index + 1) {
+            if let Some((network, mask)) = other.network() {
+                if scope.overlaps(network, mask) {
+                    findings.add(
+                        Severity::Error,
+                        &scope.cidr(),
+                        format!("overlaps with {}", other.cidr()),
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub fn lint(scopes: &[Scope], globals: &Globals) -> Vec<Finding> {
+    let mut findings = Findings(Vec::new());
+    check_globals(&mut findings, globals);
+    for scope in scopes.iter() {
+        check_scope(&mut findings, scope, globals);
+    }
+    check_overlaps(&mut findings, scopes);
+    findings.0
+}
+
+fn table_format() -> format::TableFormat {
+    format::FormatBuilder::new()
+        .column_separator(' ')
+        .separator(
+            format::LinePosition::Title,
+            format::LineSeparator::new('-', ' ', ' ', ' '),
+        )
+        .padding(1, 1)
+        .build()
+}
+
+pub fn print_findings(findings: &[Finding]) {
+    if findings.is_empty() {
+        println!("{}", "No problems found".green());
+        return;
+    }
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!(b -> "Severity", b -> "Subject", b -> "Message"));
+    for finding in findings.iter() {
+        let severity = match finding.severity {
+            Severity::Error => Cell::new(&finding.severity.to_string()).style_spec("Fr"),
+            Severity::Warning => Cell::new(&finding.severity.to_string()).style_spec("Fy"),
+            Severity::Info => Cell::new(&finding.severity.to_string()),
+        };
+        let mut row: Row = row!(&finding.subject, &finding.message);
+        row.insert_cell(0, severity);
+        table.add_row(row);
+    }
+    table.printstd();
+}
+
+// Returns an error when anything at or above `fail_on` was found, so CI jobs
+// get a non-zero exit code.
+pub fn check_findings(findings: &[Finding], fail_on: Severity) -> Result<(), DhcpctlError> {
+    let count = findings.iter().filter(|x| x.severity >= fail_on).count();
+    if count > 0 {
+        return Err(DhcpctlError::ProblemsFound(count));
+    }
+    Ok(())
+}
+
+pub async fn run_lint(fail_on: Severity) -> Result<(), DhcpctlError> {
+    let (scopes, globals): (Vec<Scope>, Globals) = tokio::try_join!(
+        reqwest_handler::run("/config/scopes"),
+        reqwest_handler::run("/config/globals")
+    )?;
+
+    let findings = lint(&scopes, &globals);
+    print_findings(&findings);
+    check_findings(&findings, fail_on)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn globals() -> Globals {
+        serde_json::from_value(json!({
+            "authoritative": true,
+            "default-lease-time": "600",
+            "max-lease-time": "7200",
+            "options": {"domain-name-servers": ["10.0.0.2"]}
+        }))
+        .unwrap()
+    }
+
+    fn scope(changes: serde_json::Value) -> Scope {
+        let mut value = json!({
+            "ip": "10.0.1.0",
+            "subnet": "255.255.255.0",
+            "range": {"start": "10.0.1.100", "end": "10.0.1.199"},
+            "options": {
+                "subnet-mask": "255.255.255.0",
+                "broadcast-address": "10.0.1.255",
+                "routers": "10.0.1.1"
+            }
+        });
+        for (name, change) in changes.as_object().unwrap() {
+            match (value.get_mut(name), change) {
+                (Some(serde_json::Value::Object(current)), serde_json::Value::Object(change)) => {
+                    current.extend(change.clone())
+                }
+                _ => value[name] = change.clone(),
+            }
+        }
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn messages(scopes: &[Scope], globals: &Globals) -> Vec<(Severity, String)> {
+        lint(scopes, globals)
+            .into_iter()
+            .map(|x| (x.severity, x.message))
+            .collect()
+    }
+
+    #[test]
+    fn clean_scope_has_no_findings() {
+        assert!(messages(&[scope(json!({}))], &globals()).is_empty());
+    }
+
+    #[test]
+    fn unset_masks_are_derived_by_dhcpd() {
+        let scope = scope(json!({"options": {"subnet-mask": "", "broadcast-address": ""}}));
+        assert!(messages(&[scope], &globals()).is_empty());
+    }
+
+    #[test]
+    fn wrong_broadcast_and_subnet_mask() {
+        let scope = scope(
+            json!({"options": {"subnet-mask": "255.255.0.0", "broadcast-address": "10.0.1.254"}}),
+        );
+        assert_eq!(
+            messages(&[scope], &globals()),
+            vec![
+                (
+                    Severity::Error,
+                    "broadcast-address 10.0.1.254 does not match the mask, expected 10.0.1.255"
+                        .to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "subnet-mask option 255.255.0.0 differs from the subnet netmask 255.255.255.0"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn range_outside_the_subnet() {
+        let scope = scope(json!({"range": {"start": "10.0.1.200", "end": "10.0.2.10"}}));
+        assert_eq!(
+            messages(&[scope], &globals()),
+            vec![
+                (
+                    Severity::Error,
+                    "range end 10.0.2.10 is outside the subnet".to_string()
+                ),
+                (
+                    Severity::Error,
+                    "range 10.0.1.200 - 10.0.2.10 includes the network or broadcast address"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        let scope = scope(json!({"range": [
+            {"start": "10.0.1.100", "end": "10.0.1.150"},
+            {"start": "10.0.1.140", "end": "10.0.1.199"}
+        ]}));
+        assert_eq!(
+            messages(&[scope], &globals()),
+            vec![(
+                Severity::Error,
+                "ranges 10.0.1.100 - 10.0.1.150 and 10.0.1.140 - 10.0.1.199 overlap".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn routers() {
+        let inside = scope(json!({"options": {"routers": "10.0.1.120"}}));
+        let outside = scope(json!({"options": {"routers": "10.0.2.1"}}));
+        let missing = scope(json!({"options": {"routers": ""}}));
+        assert_eq!(
+            messages(&[inside], &globals()),
+            vec![(
+                Severity::Error,
+                "router 10.0.1.120 is inside the dynamic range".to_string()
+            )]
+        );
+        assert_eq!(
+            messages(&[outside], &globals()),
+            vec![(
+                Severity::Error,
+                "router 10.0.2.1 is outside the subnet".to_string()
+            )]
+        );
+        assert_eq!(
+            messages(&[missing], &globals()),
+            vec![(Severity::Warning, "no routers set".to_string())]
+        );
+    }
+
+    #[test]
+    fn dns_servers_fall_back_to_the_globals() {
+        let mut globals = globals();
+        globals.options = None;
+        assert_eq!(
+            messages(&[scope(json!({}))], &globals),
+            vec![(
+                Severity::Warning,
+                "no domain-name-servers in the scope or globals".to_string()
+            )]
+        );
+        let with_dns = scope(json!({"options": {"domain-name-servers": ["10.0.0.2"]}}));
+        assert!(messages(&[with_dns], &globals).is_empty());
+    }
+
+    #[test]
+    fn lease_times() {
+        let scope = scope(json!({"default-lease-time": "9000"}));
+        assert_eq!(
+            messages(&[scope], &globals()),
+            vec![(
+                Severity::Error,
+                "default-lease-time 9000 is greater than max-lease-time 7200".to_string()
+            )]
+        );
+        let mut globals = globals();
+        globals.max_lease_time = Some("2h".to_string());
+        globals.authoritative = None;
+        assert_eq!(
+            messages(&[], &globals),
+            vec![
+                (
+                    Severity::Error,
+                    "max-lease-time '2h' is not a number of seconds".to_string()
+                ),
+                (Severity::Info, "server is not authoritative".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn global_lease_times_reported_once() {
+        let mut globals = globals();
+        globals.default_lease_time = Some("10".to_string());
+        globals.max_lease_time = Some("2h".to_string());
+        let findings = lint(&[scope(json!({}))], &globals);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|x| x.subject == "globals"));
+
+        // The inherited default is still compared with the scope's own max.
+        let findings = lint(&[scope(json!({"max-lease-time": "5"}))], &globals);
+        let scoped: Vec<&String> = findings
+            .iter()
+            .filter(|x| x.subject != "globals")
+            .map(|x| &x.message)
+            .collect();
+        assert_eq!(
+            scoped,
+            vec!["default-lease-time 10 is greater than max-lease-time 5"]
+        );
+    }
+
+    #[test]
+    fn overlapping_subnets() {
+        let wide = scope(
+            json!({"ip": "10.0.0.0", "subnet": "255.255.0.0", "range": [], "options": {"subnet-mask": "255.255.0.0", "broadcast-address": "10.0.255.255", "routers": "10.0.0.1"}}),
+        );
+        let findings = lint(&[wide, scope(json!({}))], &globals());
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[1].subject, "10.0.0.0/16");
+        assert_eq!(findings[1].message, "overlaps with 10.0.1.0/24");
+    }
+
+    #[test]
+    fn fail_on_threshold() {
+        let findings = lint(&[scope(json!({"options": {"routers": ""}}))], &globals());
+        assert!(check_findings(&findings, Severity::Error).is_ok());
+        assert!(check_findings(&findings, Severity::Warning).is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use super::config;
+use super::config::Profile;
+use super::error::DhcpctlError;
+use super::leases::Lease;
+use super::watcher::{EventKind, LeaseEvent, Watcher};
+use colored::Colorize;
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Outgoing, QoS};
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+
+pub struct MqttSettings {
+    pub broker: String,
+    pub server: Option<String>,
+    pub prefix: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub homeassistant: bool,
+    pub interval: u64,
+    pub once: bool,
+}
+
+fn parse_broker(broker: &str) -> Result<(String, u16), DhcpctlError> {
+    let address = broker
+        .trim_start_matches("tcp://")
+        .trim_start_matches("mqtt://")
+        .trim_end_matches('/');
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse::<u16>()
+                .map_err(|_| DhcpctlError::NotValidBroker)?,
+        ),
+        None => (address, 1883),
+    };
+    if host.is_empty() || host.contains('/') {
+        return Err(DhcpctlError::NotValidBroker);
+    }
+    Ok((host.to_string(), port))
+}
+
+// Topic levels can't contain '/', '+' or '#', so the API host is cleaned up
+// before it's used as the server name.
+fn server_name(profile: &Profile) -> String {
+    let host = reqwest::Url::parse(&profile.api_url)
+        .ok()
+        .and_then(|x| x.host_str().map(|x| x.to_string()))
+        .unwrap_or_else(|| "default".to_string());
+    topic_level(&host)
+}
+
+fn topic_level(value: &str) -> String {
+    value
+        .chars()
+        .map(|x| match x {
+            '/' | '+' | '#' | ' ' => '_',
+            _ => x,
+        })
+        .collect()
+}
+
+struct Publisher {
+    client: AsyncClient,
+    base: String,
+    server: String,
+    homeassistant: bool,
+    discovered: HashSet<String>,
+}
+
+impl Publisher {
+    fn lease_topic(&self, mac: &str) -> String {
+        format!("{}/leases/{}", self.base, mac)
+    }
+
+    fn presence_topic(&self, mac: &str) -> String {
+        format!("{}/presence/{}", self.base, mac)
+    }
+
+    async fn publish_lease(&self, lease: &Lease) -> Result<(), DhcpctlError> {
+        let mac = lease.hardware_ethernet.to_lowercase();
+        self.client
+            .publish(
+                self.lease_topic(&mac),
+                QoS::AtLeastOnce,
+                true,
+                serde_json::to_vec(lease)?,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn publish_presence(&self, mac: &str, home: bool) -> Result<(), DhcpctlError> {
+        let state = if home { "home" } else { "not_home" };
+        self.client
+            .publish(self.presence_topic(mac), QoS::AtLeastOnce, true, state)
+            .await?;
+        Ok(())
+    }
+
+    async fn publish_event(&self, event: &LeaseEvent) -> Result<(), DhcpctlError> {
+        self.client
+            .publish(
+                format!("{}/events", self.base),
+                QoS::AtLeastOnce,
+                false,
+                serde_json::to_vec(event)?,
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Home Assistant picks up device trackers from retained config messages
+    // under the discovery prefix. Each MAC is announced once per run.
+    async fn publish_discovery(&mut self, lease: &Lease) -> Result<(), DhcpctlError> {
+        let mac = lease.hardware_ethernet.to_lowercase();
+        if !self.homeassistant || self.discovered.contains(&mac) {
+            return Ok(());
+        }
+
+        let object_id = format!("dhcpctl_{}_{}", self.server, mac.replace(':', ""));
+        let name = lease.client_hostname.clone().unwrap_or_else(|| mac.clone());
+        let payload = json!({
+            "name": name,
+            "unique_id": object_id,
+            "state_topic": self.presence_topic(&mac),
+            "json_attributes_topic": self.lease_topic(&mac),
+            "payload_home": "home",
+            "payload_not_home": "not_home",
+            "source_type": "router",
+            "device": {
+                "name": name,
+                "connections": [["mac", mac]],
+            },
+        });
+        self.client
+            .publish(
+                format!("homeassistant/device_tracker/{}/config", object_id),
+                QoS::AtLeastOnce,
+                true,
+                serde_json::to_vec(&payload)?,
+            )
+            .await?;
+        self.discovered.insert(mac);
+        Ok(())
+    }
+
+    // Active leases are published last, so a MAC with an old expired lease
+    // and a new active one ends up with the active lease retained.
+    async fn publish_leases(&mut self, leases: &[Lease]) -> Result<(), DhcpctlError> {
+        let mut ordered: Vec<&Lease> = leases.iter().collect();
+        ordered.sort_by_key(|x| x.binding_state == "active");
+        for lease in ordered {
+            self.publish_lease(lease).await?;
+            if lease.binding_state == "active" {
+                self.publish_discovery(lease).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn drive(mut eventloop: EventLoop) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => return,
+                Ok(_) => {}
+                Err(error) => {
+                    eprintln!("{} {}", "MQTT connection error:".red(), error);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    })
+}
+
+pub async fn publish(settings: MqttSettings) -> Result<(), DhcpctlError> {
+    let profile: Profile = config::load_profile()?;
+    let (host, port) = parse_broker(&settings.broker)?;
+    let server = settings
+        .server
+        .as_deref()
+        .map(topic_level)
+        .unwrap_or_else(|| server_name(&profile));
+
+    let mut options = MqttOptions::new(format!("dhcpctl-{}", server), host, port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = settings.username {
+        options.set_credentials(username, settings.password.unwrap_or_default());
+    }
+
+    let (client, eventloop) = AsyncClient::new(options, 100);
+    let connection = drive(eventloop);
+
+    let mut publisher = Publisher {
+        client,
+        base: format!("{}/{}", settings.prefix, server),
+        server,
+        homeassistant: settings.homeassistant,
+        discovered: HashSet::new(),
+    };
+    // The snapshot file is kept per profile by the watcher.
+    let mut watcher = Watcher::new("mqtt", false)?;
+    let mut first = true;
+
+    loop {
+        match watcher.poll().await {
+            Ok(events) => {
+                publisher.publish_leases(&watcher.leases).await?;
+
+                if first {
+                    for lease in watcher
+                        .leases
+                        .iter()
+                        .filter(|x| x.binding_state == "active")
+                    {
+                        publisher
+                            .publish_presence(&lease.hardware_ethernet.to_lowercase(), true)
+                            .await?;
+                    }
+                    first = false;
+                }
+
+                for event in events.iter() {
+                    publisher.publish_event(event).await?;
+                    match event.event {
+                        EventKind::NewMac => publisher.publish_presence(&event.mac, true).await?,
+                        // A MAC can hold more than one lease, it's only gone
+                        // with the last one.
+                        EventKind::LeaseExpired
+                            if !watcher.leases.iter().any(|x| {
+                                x.binding_state == "active"
+                                    && x.hardware_ethernet.eq_ignore_ascii_case(&event.mac)
+                            }) =>
+                        {
+                            publisher.publish_presence(&event.mac, false).await?
+                        }
+                        _ => {}
+                    }
+                }
+                println!(
+                    "Published {} leases and {} events to {}",
+                    watcher.leases.len(),
+                    events.len(),
+                    publisher.base.green()
+                );
+            }
+            Err(error) => eprintln!("{} {}", "Polling leases failed:".red(), error),
+        }
+
+        if settings.once {
+            publisher.client.disconnect().await?;
+            let _ = tokio::time::timeout(Duration::from_secs(10), connection).await;
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(settings.interval)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brokers() {
+        let broker = |x| parse_broker(x).ok();
+        assert_eq!(
+            broker("tcp://localhost:1883"),
+            Some(("localhost".to_string(), 1883))
+        );
+        assert_eq!(
+            broker("mqtt://10.0.0.5:8883/"),
+            Some(("10.0.0.5".to_string(), 8883))
+        );
+        assert_eq!(
+            broker("tcp://broker.lan"),
+            Some(("broker.lan".to_string(), 1883))
+        );
+        assert_eq!(
+            broker("mqtt://broker.lan"),
+            Some(("broker.lan".to_string(), 1883))
+        );
+        assert_eq!(
+            broker("broker.lan:1884"),
+            Some(("broker.lan".to_string(), 1884))
+        );
+        assert!(matches!(
+            parse_broker("tcp://broker.lan:port"),
+            Err(DhcpctlError::NotValidBroker)
+        ));
+        assert!(matches!(
+            parse_broker("tcp://:1883"),
+            Err(DhcpctlError::NotValidBroker)
+        ));
+        assert!(matches!(
+            parse_broker("tcp://broker.lan/dhcp"),
+            Err(DhcpctlError::NotValidBroker)
+        ));
+    }
+
+    #[test]
+    fn topic_levels_are_sanitized() {
+        assert_eq!(topic_level("00:1a:2b:3c:4d:5e"), "00:1a:2b:3c:4d:5e");
+        assert_eq!(topic_level("dhcp/+#1 a"), "dhcp___1_a");
+    }
+
+    #[test]
+    fn server_names() {
+        let name = |url: &str| {
+            server_name(&Profile {
+                api_url: url.to_string(),
+                ..Profile::default()
+            })
+        };
+        assert_eq!(
+            name("https://dhcp.example.org:8080/api"),
+            "dhcp.example.org"
+        );
+        assert_eq!(name("http://10.0.0.2"), "10.0.0.2");
+        assert_eq!(name("not a url"), "default");
+    }
+}
@@ -0,0 +1,351 @@
+use super::config;
+use super::config::ConfyConfig;
+use super::error::DhcpctlError;
+use super::lint::{self, Finding, Severity};
+use super::reqwest_handler;
+use super::scopes::Scope;
+use cidr::Ipv4Cidr;
+use colored::Colorize;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+struct Source {
+    name: String,
+    scopes: Vec<Scope>,
+}
+
+#[derive(Clone, Copy)]
+struct Interval<'a> {
+    start: u32,
+    end: u32,
+    source: &'a str,
+    scope: &'a Scope,
+}
+
+fn subnet_interval<'a>(source: &'a str, scope: &'a Scope) -> Option<Interval<'a>> {
+    let (network, _) = scope.network()?;
+    let cidr = Ipv4Cidr::new(Ipv4Addr::from(network), scope.prefix_len() as u8).ok()?;
+    Some(Interval {
+        start: u32::from(cidr.first_address()),
+        end: u32::from(cidr.last_address()),
+        source,
+        scope,
+    })
+}
+
+fn first_overlap(x: &[(u32, u32)], y: &[(u32, u32)]) -> Option<(u32, u32)> {
+    x.iter().find_map(|a| {
+        y.iter()
+            .find(|b| a.0 <= b.1 && b.0 <= a.1)
+            .map(|b| (a.0.max(b.0), a.1.min(b.1)))
+    })
+}
+
+// Sort the intervals by start address and sweep over them, only comparing
+// against intervals that are still open. Returns every overlapping pair.
+fn overlapping_pairs<'a>(mut intervals: Vec<Interval<'a>>) -> Vec<(Interval<'a>, Interval<'a>)> {
+    intervals.sort_by_key(|x| (x.start, x.end));
+    let mut open: Vec<Interval> = Vec::new();
+    let mut pairs = Vec::new();
+
+    for interval in intervals {
+        open.retain(|x| x.end >= interval.start);
+        for other in open.iter() {
+            pairs.push((*other, interval));
+        }
+        open.push(interval);
+    }
+    pairs
+}
+
+fn label(interval: &Interval) -> String {
+    format!("{}:{}", interval.source, interval.scope.cidr())
+}
+
+fn compare_options(findings: &mut Vec<Finding>, subject: &str, a: &Interval, b: &Interval) {
+    let (x, y) = (a.scope, b.scope);
+    let fields: Vec<(&str, String, String)> = vec![
+        (
+            "subnet-mask",
+            x.options.subnet_mask.clone(),
+            y.options.subnet_mask.clone(),
+        ),
+        (
+            "broadcast-address",
+            x.options.broadcast_address.clone(),
+            y.options.broadcast_address.clone(),
+        ),
+        (
+            "routers",
+            x.options.routers.clone(),
+            y.options.routers.clone(),
+        ),
+        (
+            "domain-name-servers",
+            x.options
+                .domain_name_servers
+                .clone()
+                .unwrap_or_default()
+                .join(","),
+            y.options
+                .domain_name_servers
+                .clone()
+                .unwrap_or_default()
+                .join(","),
+        ),
+        (
+            "domain-name",
+            x.options.domain_name.clone().unwrap_or_default(),
+            y.options.domain_name.clone().unwrap_or_default(),
+        ),
+        (
+            "next-server",
+            x.next_server.clone().unwrap_or_default(),
+            y.next_server.clone().unwrap_or_default(),
+        ),
+        (
+            "bootfile-name",
+            x.options.bootfile_name.clone().unwrap_or_default(),
+            y.options.bootfile_name.clone().unwrap_or_default(),
+        ),
+        (
+            "default-lease-time",
+            x.default_lease_time.clone().unwrap_or_default(),
+            y.default_lease_time.clone().unwrap_or_default(),
+        ),
+        (
+            "max-lease-time",
+            x.max_lease_time.clone().unwrap_or_default(),
+            y.max_lease_time.clone().unwrap_or_default(),
+        ),
+    ];
+
+    for (name, left, right) in fields {
+        if left != right {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                subject: subject.to_string(),
+                message: format!(
+                    "{} differs: {} has '{}', {} has '{}'",
+                    name, a.source, left, b.source, right
+                ),
+            });
+        }
+    }
+}
+
+fn check_pair(findings: &mut Vec<Finding>, a: &Interval, b: &Interval) {
+    let subject = format!("{} / {}", label(a), label(b));
+    let mut add = |severity: Severity, message: String| {
+        findings.push(Finding {
+            severity,
+            subject: subject.clone(),
+            message,
+        })
+    };
+
+    if (a.start, a.end) != (b.start, b.end) {
+        add(Severity::Error, "subnets overlap".to_string());
+        return;
+    }
+    if a.source == b.source {
+        add(
+            Severity::Error,
+            format!("subnet is defined twice on {}", a.source),
+        );
+        return;
+    }
+
+    // The same subnet on two servers is either a failover pair with identical
+    // pools or a split scope with disjoint ranges. Anything in between means
+    // both servers can hand out the same address.
+    let (x, y) = (a.scope.range_intervals(), b.scope.range_intervals());
+    if x == y {
+        add(
+            Severity::Warning,
+            "exact duplicate of subnet and range, fine for failover peers, otherwise both servers lease the same pool"
+                .to_string(),
+        );
+    } else if let Some((start, end)) = first_overlap(&x, &y) {
+        add(
+            Severity::Error,
+            format!(
+                "ranges overlap, both servers can lease {} - {}",
+                Ipv4Addr::from(start),
+                Ipv4Addr::from(end)
+            ),
+        );
+    } else {
+        add(
+            Severity::Info,
+            "split scope with disjoint ranges".to_string(),
+        );
+    }
+    compare_options(findings, &subject, a, b);
+}
+
+// Profiles that can't be reached are reported and returned by name, so the
+// rest can still be compared before the command fails.
+async fn load_sources(files: &[PathBuf]) -> Result<(Vec<Source>, Vec<String>), DhcpctlError> {
+    let conf: ConfyConfig = config::load_config()?;
+    let mut sources = Vec::new();
+    let mut failed = Vec::new();
+
+    for name in conf.profile_names() {
+        let profile = conf.profile(&name)?;
+        match reqwest_handler::run_on::<Vec<Scope>>(&profile, "/config/scopes").await {
+            Ok(scopes) => sources.push(Source { name, scopes }),
+            Err(error) => {
+                eprintln!("{} {}: {}", "Skipping profile".yellow(), name, error);
+                failed.push(name);
+            }
+        }
+    }
+
+    for file in files.iter() {
+        let scopes: Vec<Scope> = serde_json::from_str(&fs::read_to_string(file)?)?;
+        sources.push(Source {
+            name: file.display().to_string(),
+            scopes,
+        });
+    }
+    Ok((sources, failed))
+}
+
+pub async fn find_overlaps(files: &[PathBuf], fail_on: Severity) -> Result<(), DhcpctlError> {
+    let (sources, failed) = load_sources(files).await?;
+
+    let intervals: Vec<Interval> = sources
+        .iter()
+        .flat_map(|source| {
+            source
+                .scopes
+                .iter()
+                .filter_map(move |scope| subnet_interval(&source.name, scope))
+        })
+        .collect();
+
+    let mut findings = Vec::new();
+    for (a, b) in overlapping_pairs(intervals) {
+        check_pair(&mut findings, &a, &b);
+    }
+
+    println!(
+        "Compared {} scopes from {} sources\n",
+        sources.iter().map(|x| x.scopes.len()).sum::<usize>(),
+        sources.len()
+    );
+    lint::print_findings(&findings);
+    if !failed.is_empty() {
+        return Err(DhcpctlError::UnreachableProfiles(failed));
+    }
+    lint::check_findings(&findings, fail_on)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scope(cidr: &str, range: (&str, &str), routers: &str) -> Scope {
+        let (network, mask) = super::super::scopes::parse_cidr(cidr).unwrap();
+        serde_json::from_value(json!({
+            "ip": Ipv4Addr::from(network).to_string(),
+            "subnet": Ipv4Addr::from(mask).to_string(),
+            "range": {"start": range.0, "end": range.1},
+            "options": {"routers": routers}
+        }))
+        .unwrap()
+    }
+
+    fn findings(a: (&str, &Scope), b: (&str, &Scope)) -> Vec<(Severity, String)> {
+        let mut findings = Vec::new();
+        check_pair(
+            &mut findings,
+            &subnet_interval(a.0, a.1).unwrap(),
+            &subnet_interval(b.0, b.1).unwrap(),
+        );
+        findings
+            .into_iter()
+            .map(|x| (x.severity, x.message))
+            .collect()
+    }
+
+    #[test]
+    fn sweep_finds_every_overlapping_pair() {
+        let scopes = [
+            scope("10.0.1.0/24", ("10.0.1.100", "10.0.1.199"), "10.0.1.1"),
+            scope("10.1.0.0/24", ("10.1.0.100", "10.1.0.199"), "10.1.0.1"),
+            scope("10.0.0.0/16", ("10.0.0.100", "10.0.0.199"), "10.0.0.1"),
+            scope("10.0.1.0/24", ("10.0.1.100", "10.0.1.199"), "10.0.1.1"),
+            scope("10.0.2.0/24", ("10.0.2.100", "10.0.2.199"), "10.0.2.1"),
+        ];
+        let intervals = vec![
+            subnet_interval("a", &scopes[0]).unwrap(),
+            subnet_interval("a", &scopes[1]).unwrap(),
+            subnet_interval("a", &scopes[2]).unwrap(),
+            subnet_interval("b", &scopes[3]).unwrap(),
+            subnet_interval("b", &scopes[4]).unwrap(),
+        ];
+        let pairs: Vec<(String, String)> = overlapping_pairs(intervals)
+            .iter()
+            .map(|(x, y)| (label(x), label(y)))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("a:10.0.0.0/16".to_string(), "a:10.0.1.0/24".to_string()),
+                ("a:10.0.0.0/16".to_string(), "b:10.0.1.0/24".to_string()),
+                ("a:10.0.1.0/24".to_string(), "b:10.0.1.0/24".to_string()),
+                ("a:10.0.0.0/16".to_string(), "b:10.0.2.0/24".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlaps_and_duplicates() {
+        let wide = scope("10.0.0.0/16", ("10.0.0.100", "10.0.0.199"), "10.0.0.1");
+        let narrow = scope("10.0.1.0/24", ("10.0.1.100", "10.0.1.199"), "10.0.1.1");
+        assert_eq!(
+            findings(("a", &wide), ("b", &narrow)),
+            vec![(Severity::Error, "subnets overlap".to_string())]
+        );
+        assert_eq!(
+            findings(("a", &narrow), ("a", &narrow)),
+            vec![(Severity::Error, "subnet is defined twice on a".to_string())]
+        );
+    }
+
+    #[test]
+    fn failover_and_split_scopes() {
+        let primary = scope("10.0.1.0/24", ("10.0.1.100", "10.0.1.199"), "10.0.1.1");
+        let peer = scope("10.0.1.0/24", ("10.0.1.100", "10.0.1.199"), "10.0.1.1");
+        let (severity, _) = &findings(("a", &primary), ("b", &peer))[0];
+        assert_eq!(*severity, Severity::Warning);
+
+        let split = scope("10.0.1.0/24", ("10.0.1.200", "10.0.1.250"), "10.0.1.1");
+        assert_eq!(
+            findings(("a", &primary), ("b", &split)),
+            vec![(
+                Severity::Info,
+                "split scope with disjoint ranges".to_string()
+            )]
+        );
+
+        let clash = scope("10.0.1.0/24", ("10.0.1.150", "10.0.1.250"), "10.0.1.254");
+        assert_eq!(
+            findings(("a", &primary), ("b", &clash)),
+            vec![
+                (
+                    Severity::Error,
+                    "ranges overlap, both servers can lease 10.0.1.150 - 10.0.1.199".to_string()
+                ),
+                (
+                    Severity::Warning,
+                    "routers differs: a has '10.0.1.1', b has '10.0.1.254'".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,498 @@
+use super::audit;
+use super::error::DhcpctlError;
+use super::hosts::Host;
+use super::reqwest_handler;
+use super::scopes::Scope;
+use colored::Colorize;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// Only the sections present in the file are managed, so a file with just
+// `hosts:` never touches the scopes. Within a scope or host, fields left out
+// or empty keep their live value.
+#[derive(Deserialize)]
+struct Desired {
+    scopes: Option<Vec<Scope>>,
+    hosts: Option<Vec<Host>>,
+}
+
+#[derive(PartialEq)]
+enum Action {
+    Create,
+    Update,
+    Delete,
+}
+
+struct Change {
+    action: Action,
+    kind: &'static str,
+    label: String,
+    path: String,
+    body: Value,
+    fields: Vec<(String, Option<Value>, Option<Value>)>,
+}
+
+// One scope or host, keyed the way the API addresses it.
+struct Item {
+    key: String,
+    label: String,
+    value: Value,
+}
+
+fn is_unset(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(x) => x.is_empty(),
+        Value::Array(x) => x.is_empty(),
+        Value::Object(x) => x.is_empty(),
+        _ => false,
+    }
+}
+
+// Leaves as dotted paths, eg. `options.routers` or `range[0].start`. Lists of
+// plain values like DNS servers are compared as a whole.
+pub fn flatten(prefix: &str, value: &Value, leaves: &mut BTreeMap<String, Value>) {
+    let join = |name: &str| {
+        if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", prefix, name)
+        }
+    };
+    match value {
+        Value::Object(map) => {
+            for (name, value) in map.iter() {
+                flatten(&join(name), value, leaves);
+            }
+        }
+        Value::Array(items) if items.iter().any(|x| x.is_object() || x.is_array()) => {
+            for (index, value) in items.iter().enumerate() {
+                flatten(&format!("{}[{}]", prefix, index), value, leaves);
+            }
+        }
+        x if !is_unset(x) => {
+            leaves.insert(prefix.to_string(), x.clone());
+        }
+        _ => {}
+    }
+}
+
+// Drops unset fields from a request body, so the API sees only real values.
+fn prune(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.values_mut().for_each(prune);
+            *map = std::mem::take(map)
+                .into_iter()
+                .filter(|(_, x)| !is_unset(x))
+                .collect();
+        }
+        Value::Array(items) => items.iter_mut().for_each(prune),
+        _ => {}
+    }
+}
+
+fn merge(live: &mut Value, desired: &Value) {
+    match (live, desired) {
+        (Value::Object(live), Value::Object(desired)) => {
+            for (name, value) in desired.iter().filter(|(_, x)| !is_unset(x)) {
+                match live.get_mut(name) {
+                    Some(existing) if existing.is_object() && value.is_object() => {
+                        merge(existing, value)
+                    }
+                    _ => {
+                        live.insert(name.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (live, desired) => {
+            if !is_unset(desired) {
+                *live = desired.clone();
+            }
+        }
+    }
+}
+
+fn diff_fields(old: &Value, new: &Value) -> Vec<(String, Option<Value>, Option<Value>)> {
+    let (mut old_leaves, mut new_leaves) = (BTreeMap::new(), BTreeMap::new());
+    flatten("", old, &mut old_leaves);
+    flatten("", new, &mut new_leaves);
+
+    let mut names: Vec<&String> = old_leaves.keys().chain(new_leaves.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(|x| old_leaves.get(*x) != new_leaves.get(*x))
+        .map(|x| {
+            (
+                x.clone(),
+                old_leaves.get(x).cloned(),
+                new_leaves.get(x).cloned(),
+            )
+        })
+        .collect()
+}
+
+fn to_items<T: Serialize>(
+    values: &[T],
+    key: impl Fn(&T) -> (String, String),
+) -> Result<Vec<Item>, DhcpctlError> {
+    let mut items = Vec::new();
+    for value in values.iter() {
+        let (key, label) = key(value);
+        items.push(Item {
+            key,
+            label,
+            value: serde_json::to_value(value)?,
+        });
+    }
+    Ok(items)
+}
+
+fn compare(
+    kind: &'static str,
+    collection: &str,
+    live: Vec<Item>,
+    desired: Vec<Item>,
+) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for item in desired.iter() {
+        let mut body = item.value.clone();
+        prune(&mut body);
+        match live.iter().find(|x| x.key == item.key) {
+            Some(current) => {
+                let mut merged = current.value.clone();
+                merge(&mut merged, &body);
+                let fields = diff_fields(&current.value, &merged);
+                prune(&mut merged);
+                if !fields.is_empty() {
+                    changes.push(Change {
+                        action: Action::Update,
+                        kind,
+                        label: item.label.clone(),
+                        path: format!(
+                            "{}/{}",
+                            collection,
+                            reqwest_handler::path_segment(&item.key)
+                        ),
+                        body: merged,
+                        fields,
+                    });
+                }
+            }
+            None => changes.push(Change {
+                action: Action::Create,
+                kind,
+                label: item.label.clone(),
+                path: collection.to_string(),
+                fields: diff_fields(&Value::Null, &body),
+                body,
+            }),
+        }
+    }
+    for item in live.iter() {
+        if !desired.iter().any(|x| x.key == item.key) {
+            changes.push(Change {
+                action: Action::Delete,
+                kind,
+                label: item.label.clone(),
+                path: format!(
+                    "{}/{}",
+                    collection,
+                    reqwest_handler::path_segment(&item.key)
+                ),
+                body: Value::Null,
+                fields: Vec::new(),
+            });
+        }
+    }
+    changes
+}
+
+fn scope_key(scope: &Scope) -> (String, String) {
+    (scope.ip.clone(), scope.cidr())
+}
+
+fn host_key(host: &Host) -> (String, String) {
+    (host.name.clone(), host.name.clone())
+}
+
+// Scopes are created before the hosts that may live in them and deleted
+// after them.
+async fn build_plan(file: &Path) -> Result<Vec<Change>, DhcpctlError> {
+    let desired: Desired = serde_yaml::from_str(&fs::read_to_string(file)?)?;
+
+    let mut scope_changes = Vec::new();
+    if let Some(scopes) = &desired.scopes {
+        let live: Vec<Scope> = reqwest_handler::run("/config/scopes").await?;
+        scope_changes = compare(
+            "scope",
+            "/config/scopes",
+            to_items(&live, scope_key)?,
+            to_items(scopes, scope_key)?,
+        );
+    }
+    let mut host_changes = Vec::new();
+    if let Some(hosts) = &desired.hosts {
+        let live: Vec<Host> = reqwest_handler::run("/config/hosts").await?;
+        host_changes = compare(
+            "host",
+            "/config/hosts",
+            to_items(&live, host_key)?,
+            to_items(hosts, host_key)?,
+        );
+    }
+
+    let (scope_deletes, mut changes): (Vec<Change>, Vec<Change>) = scope_changes
+        .into_iter()
+        .partition(|x| x.action == Action::Delete);
+    changes.extend(host_changes);
+    changes.extend(scope_deletes);
+    Ok(changes)
+}
+
+fn print_plan(changes: &[Change], file: &Path) {
+    if changes.is_empty() {
+        println!(
+            "No changes. The live configuration matches {}.",
+            file.display()
+        );
+        return;
+    }
+
+    let show = |value: &Option<Value>| {
+        value
+            .as_ref()
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| "(unset)".to_string())
+    };
+    for change in changes.iter() {
+        let title = format!("{} {}", change.kind, change.label);
+        match change.action {
+            Action::Create => println!("{} {}", "+".green().bold(), title.bold()),
+            Action::Update => println!("{} {}", "~".yellow().bold(), title.bold()),
+            Action::Delete => println!("{} {}", "-".red().bold(), title.bold()),
+        }
+        for (name, old, new) in change.fields.iter() {
+            match change.action {
+                Action::Create => println!("    {} {} = {}", "+".green(), name, show(new)),
+                _ => println!(
+                    "    {} {}: {} -> {}",
+                    "~".yellow(),
+                    name,
+                    show(old),
+                    show(new)
+                ),
+            }
+        }
+    }
+
+    let count = |action: Action| changes.iter().filter(|x| x.action == action).count();
+    println!(
+        "\nPlan: {} to add, {} to change, {} to destroy.",
+        count(Action::Create),
+        count(Action::Update),
+        count(Action::Delete)
+    );
+}
+
+pub async fn plan(file: &Path) -> Result<(), DhcpctlError> {
+    let changes = build_plan(file).await?;
+    print_plan(&changes, file);
+    Ok(())
+}
+
+pub async fn apply(file: &Path, yes: bool) -> Result<(), DhcpctlError> {
+    let changes = build_plan(file).await?;
+    print_plan(&changes, file);
+    if changes.is_empty() {
+        return Ok(());
+    }
+    if !audit::confirm(&format!("\nApply {} change(s)?", changes.len()), yes)? {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    for change in changes.iter() {
+        match change.action {
+            Action::Create => {
+                reqwest_handler::write(Method::POST, &change.path, Some(&change.body)).await?
+            }
+            Action::Update => {
+                reqwest_handler::write(Method::PUT, &change.path, Some(&change.body)).await?
+            }
+            Action::Delete => {
+                reqwest_handler::write::<()>(Method::DELETE, &change.path, None).await?
+            }
+        }
+        println!("{} {} {}", "Applied".green(), change.kind, change.label);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(key: &str, value: Value) -> Item {
+        Item {
+            key: key.to_string(),
+            label: key.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn flatten_uses_dotted_paths_and_keeps_plain_lists_whole() {
+        let mut leaves = BTreeMap::new();
+        flatten(
+            "",
+            &json!({
+                "range": [{"start": "10.0.1.100", "end": "10.0.1.199"}],
+                "options": {"routers": "10.0.1.1", "domain-name-servers": ["10.0.0.2", "10.0.0.3"]},
+                "next-server": null,
+                "filename": ""
+            }),
+            &mut leaves,
+        );
+        assert_eq!(leaves.get("range[0].start"), Some(&json!("10.0.1.100")));
+        assert_eq!(leaves.get("options.routers"), Some(&json!("10.0.1.1")));
+        assert_eq!(
+            leaves.get("options.domain-name-servers"),
+            Some(&json!(["10.0.0.2", "10.0.0.3"]))
+        );
+        assert_eq!(leaves.len(), 4);
+    }
+
+    #[test]
+    fn merge_keeps_live_values_left_out_of_the_file() {
+        let mut live = json!({"options": {"routers": "10.0.1.1", "domain-name": "example.org"}});
+        merge(
+            &mut live,
+            &json!({"options": {"routers": "10.0.1.254", "domain-name": ""}}),
+        );
+        assert_eq!(
+            live,
+            json!({"options": {"routers": "10.0.1.254", "domain-name": "example.org"}})
+        );
+    }
+
+    #[test]
+    fn diff_fields_lists_changed_added_and_removed_leaves() {
+        let fields = diff_fields(
+            &json!({"a": "1", "b": "2"}),
+            &json!({"a": "1", "b": "3", "c": "4"}),
+        );
+        assert_eq!(
+            fields,
+            vec![
+                ("b".to_string(), Some(json!("2")), Some(json!("3"))),
+                ("c".to_string(), None, Some(json!("4"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn compare_creates_items_missing_from_the_server() {
+        let changes = compare(
+            "scope",
+            "/config/scopes",
+            Vec::new(),
+            vec![item(
+                "10.0.1.0",
+                json!({"ip": "10.0.1.0", "next-server": null}),
+            )],
+        );
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].action == Action::Create);
+        assert_eq!(changes[0].path, "/config/scopes");
+        assert_eq!(changes[0].body, json!({"ip": "10.0.1.0"}));
+    }
+
+    #[test]
+    fn compare_updates_changed_fields() {
+        let changes = compare(
+            "scope",
+            "/config/scopes",
+            vec![item(
+                "10.0.1.0",
+                json!({"ip": "10.0.1.0", "options": {"routers": "10.0.1.1"}}),
+            )],
+            vec![item(
+                "10.0.1.0",
+                json!({"ip": "10.0.1.0", "options": {"routers": "10.0.1.254"}}),
+            )],
+        );
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].action == Action::Update);
+        assert_eq!(changes[0].path, "/config/scopes/10.0.1.0");
+        assert_eq!(
+            changes[0].fields,
+            vec![(
+                "options.routers".to_string(),
+                Some(json!("10.0.1.1")),
+                Some(json!("10.0.1.254"))
+            )]
+        );
+    }
+
+    #[test]
+    fn compare_keeps_fields_left_out_of_the_file() {
+        let live = json!({"ip": "10.0.1.0", "options": {"routers": "10.0.1.1"}, "next-server": "10.0.0.5"});
+        let unchanged = compare(
+            "scope",
+            "/config/scopes",
+            vec![item("10.0.1.0", live.clone())],
+            vec![item(
+                "10.0.1.0",
+                json!({"ip": "10.0.1.0", "options": {"routers": "10.0.1.1"}}),
+            )],
+        );
+        assert!(unchanged.is_empty());
+
+        let changes = compare(
+            "scope",
+            "/config/scopes",
+            vec![item("10.0.1.0", live)],
+            vec![item(
+                "10.0.1.0",
+                json!({"ip": "10.0.1.0", "options": {"routers": "10.0.1.254"}}),
+            )],
+        );
+        assert_eq!(changes[0].body["next-server"], json!("10.0.0.5"));
+    }
+
+    #[test]
+    fn compare_deletes_live_items_missing_from_the_file() {
+        let changes = compare(
+            "host",
+            "/config/hosts",
+            vec![
+                item("printer", json!({"name": "printer"})),
+                item("nas", json!({"name": "nas"})),
+            ],
+            vec![item("nas", json!({"name": "nas"}))],
+        );
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].action == Action::Delete);
+        assert_eq!(changes[0].path, "/config/hosts/printer");
+    }
+
+    #[test]
+    fn compare_encodes_the_key_in_the_path() {
+        let changes = compare(
+            "host",
+            "/config/hosts",
+            vec![item("lab printer/2", json!({"name": "lab printer/2"}))],
+            Vec::new(),
+        );
+        assert_eq!(changes[0].path, "/config/hosts/lab%20printer%2F2");
+    }
+}
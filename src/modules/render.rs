@@ -0,0 +1,409 @@
+use super::dhcpd_conf;
+use super::error::DhcpctlError;
+use super::globals::Globals;
+use super::hosts::Host;
+use super::lint::{self, Severity};
+use super::scopes::{Pool, Range, Scope};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+#[derive(Deserialize, Default)]
+pub struct Network {
+    #[serde(default)]
+    pub globals: Globals,
+    #[serde(default)]
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub hosts: Vec<Host>,
+}
+
+// YAML unless the file ends in .toml.
+pub fn load_network(file: &Path) -> Result<Network, DhcpctlError> {
+    let text = fs::read_to_string(file)?;
+    let mut network: Network = match file.extension().and_then(|x| x.to_str()) {
+        Some("toml") => toml::from_str(&text)?,
+        _ => serde_yaml::from_str(&text)?,
+    };
+    for scope in network.scopes.iter_mut() {
+        derive_masks(scope);
+    }
+    Ok(network)
+}
+
+// dhcpd derives these from the subnet declaration, so they may be left out.
+pub fn derive_masks(scope: &mut Scope) {
+    if let Some((network, mask)) = scope.network() {
+        if scope.options.subnet_mask.is_empty() {
+            scope.options.subnet_mask = scope.subnet.clone();
+        }
+        if scope.options.broadcast_address.is_empty() {
+            scope.options.broadcast_address = Ipv4Addr::from(network | !mask).to_string();
+        }
+    }
+}
+
+struct Conf {
+    lines: Vec<String>,
+    depth: usize,
+}
+
+impl Conf {
+    fn line(&mut self, text: String) {
+        self.lines
+            .push(format!("{}{}", "  ".repeat(self.depth), text));
+    }
+
+    fn open(&mut self, words: String) {
+        self.line(format!("{} {{", words));
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        self.line("}".to_string());
+    }
+
+    fn blank(&mut self) {
+        if self.lines.last().map(|x| !x.is_empty()).unwrap_or(false) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn statement(&mut self, name: &str, value: &Value) -> Result<(), DhcpctlError> {
+        check_scalar(name, value)?;
+        match value {
+            Value::Bool(true) => self.line(format!("{};", name)),
+            Value::Bool(false) => self.line(format!("not {};", name)),
+            x if is_unset(x) => {}
+            x => self.line(format!("{} {};", name, dhcpd_conf::format_value(name, x))),
+        }
+        Ok(())
+    }
+
+    fn option(&mut self, name: &str, value: &Value) -> Result<(), DhcpctlError> {
+        check_scalar(name, value)?;
+        if !is_unset(value) {
+            self.line(format!(
+                "option {} {};",
+                name,
+                dhcpd_conf::format_value(name, value)
+            ));
+        }
+        Ok(())
+    }
+
+    fn options<T: serde::Serialize>(&mut self, options: &T) -> Result<(), DhcpctlError> {
+        if let Value::Object(map) = serde_json::to_value(options)? {
+            for (name, value) in map.iter() {
+                self.option(name, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn ranges(&mut self, ranges: &[Range]) {
+        for range in ranges.iter() {
+            self.line(format!("range {} {};", range.start, range.end));
+        }
+    }
+
+    fn extra(&mut self, extra: &BTreeMap<String, Value>) -> Result<(), DhcpctlError> {
+        for (name, value) in extra.iter() {
+            self.statement(name, value)?;
+        }
+        Ok(())
+    }
+}
+
+// Nested maps have no dhcpd.conf form and would be written out as JSON.
+fn check_scalar(name: &str, value: &Value) -> Result<(), DhcpctlError> {
+    match value {
+        Value::Object(_) => Err(DhcpctlError::DhcpdConf(format!(
+            "'{}' must be a value or a list, not a map",
+            name
+        ))),
+        Value::Array(x) => x.iter().try_for_each(|x| check_scalar(name, x)),
+        _ => Ok(()),
+    }
+}
+
+fn is_unset(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(x) => x.is_empty(),
+        Value::Array(x) => x.is_empty(),
+        _ => false,
+    }
+}
+
+fn text(value: &Option<String>) -> Value {
+    value.clone().map(Value::String).unwrap_or(Value::Null)
+}
+
+fn render_globals(conf: &mut Conf, globals: &Globals) -> Result<(), DhcpctlError> {
+    if let Some(authoritative) = globals.authoritative {
+        conf.statement("authoritative", &Value::Bool(authoritative))?;
+    }
+    conf.statement("default-lease-time", &text(&globals.default_lease_time))?;
+    conf.statement("max-lease-time", &text(&globals.max_lease_time))?;
+    if let Some(options) = &globals.options {
+        conf.options(options)?;
+    }
+    conf.extra(&globals.extra)
+}
+
+fn render_pool(conf: &mut Conf, pool: &Pool) -> Result<(), DhcpctlError> {
+    conf.open("pool".to_string());
+    for rule in pool.allow.iter() {
+        conf.line(format!("allow {};", rule));
+    }
+    for rule in pool.deny.iter() {
+        conf.line(format!("deny {};", rule));
+    }
+    conf.ranges(&pool.ranges);
+    conf.extra(&pool.extra)?;
+    conf.close();
+    Ok(())
+}
+
+fn render_scope(conf: &mut Conf, scope: &Scope) -> Result<(), DhcpctlError> {
+    conf.open(format!("subnet {} netmask {}", scope.ip, scope.subnet));
+    conf.ranges(&scope.ranges);
+    conf.options(&scope.options)?;
+    conf.statement("next-server", &text(&scope.next_server))?;
+    conf.statement("default-lease-time", &text(&scope.default_lease_time))?;
+    conf.statement("max-lease-time", &text(&scope.max_lease_time))?;
+    conf.extra(&scope.extra)?;
+    for pool in scope.pools.iter() {
+        render_pool(conf, pool)?;
+    }
+    conf.close();
+    Ok(())
+}
+
+// Subnets of a shared network are rendered together inside its block, in
+// the position of the first member.
+pub fn render(network: &Network, source: &str) -> Result<String, DhcpctlError> {
+    let mut conf = Conf {
+        lines: vec![format!("# Generated by dhcpctl from {}", source)],
+        depth: 0,
+    };
+    conf.blank();
+    render_globals(&mut conf, &network.globals)?;
+
+    let mut rendered: Vec<&str> = Vec::new();
+    for scope in network.scopes.iter() {
+        match &scope.shared_network {
+            Some(name) if rendered.contains(&name.as_str()) => continue,
+            Some(name) => {
+                rendered.push(name);
+                conf.blank();
+                conf.open(format!("shared-network {}", dhcpd_conf::word(name)));
+                for member in network
+                    .scopes
+                    .iter()
+                    .filter(|x| x.shared_network.as_ref() == Some(name))
+                {
+                    render_scope(&mut conf, member)?;
+                }
+                conf.close();
+            }
+            None => {
+                conf.blank();
+                render_scope(&mut conf, scope)?;
+            }
+        }
+    }
+
+    for host in network.hosts.iter() {
+        for (name, value) in host.options.iter().chain(host.extra.iter()) {
+            check_scalar(name, value)?;
+        }
+        conf.blank();
+        conf.lines.push(host.stanza());
+    }
+    Ok(conf.lines.join("\n"))
+}
+
+// The findings go to stderr so they don't end up in the redirected config.
+// Nothing is rendered when a finding reaches `fail_on`.
+pub fn print_config(file: &Path, fail_on: Severity) -> Result<(), DhcpctlError> {
+    let network = load_network(file)?;
+    let findings = lint::lint(&network.scopes, &network.globals);
+    for finding in findings.iter() {
+        eprintln!(
+            "{}: {}: {}",
+            finding.severity, finding.subject, finding.message
+        );
+    }
+    lint::check_findings(&findings, fail_on)?;
+
+    let source = file
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    println!("{}", render(&network, &source)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const YAML: &str = r#"
+globals:
+  authoritative: true
+  default-lease-time: 600
+  max-lease-time: "7200"
+  ddns-update-style: none
+  options:
+    domain-name: example.org
+    domain-name-servers: [10.0.0.2, 10.0.0.3]
+scopes:
+  - ip: 10.0.1.0
+    subnet: 255.255.255.0
+    shared-network: vlan10
+    range: { start: 10.0.1.100, end: 10.0.1.199 }
+    options: { routers: 10.0.1.1 }
+  - ip: 10.0.9.0
+    subnet: 255.255.255.0
+    options: { routers: 10.0.9.1 }
+    pools:
+      - range: { start: 10.0.9.50, end: 10.0.9.99 }
+        deny: [unknown-clients]
+  - ip: 10.0.2.0
+    subnet: 255.255.254.0
+    shared-network: vlan10
+    ranges:
+      - { start: 10.0.2.10, end: 10.0.2.250 }
+    options: { routers: 10.0.2.1, bootfile-name: pxelinux.0 }
+    next-server: 10.0.0.5
+hosts:
+  - name: printer-3
+    hardware-ethernet: 00:11:22:33:44:55
+    fixed-address: 10.0.1.23
+    options: { host-name: printer-3 }
+"#;
+
+    const TOML: &str = r#"
+[globals]
+default-lease-time = 600
+max-lease-time = 7200
+
+[[scopes]]
+ip = "10.0.1.0"
+subnet = "255.255.255.0"
+max-lease-time = 3600
+range = { start = "10.0.1.100", end = "10.0.1.199" }
+
+[scopes.options]
+routers = "10.0.1.1"
+"#;
+
+    fn write(name: &str, text: &str) -> PathBuf {
+        let file =
+            std::env::temp_dir().join(format!("dhcpctl-render-{}-{}", std::process::id(), name));
+        fs::write(&file, text).unwrap();
+        file
+    }
+
+    fn render_file(name: &str, text: &str) -> Result<String, DhcpctlError> {
+        let file = write(name, text);
+        let network = load_network(&file);
+        fs::remove_file(&file).unwrap();
+        render(&network?, name)
+    }
+
+    #[test]
+    fn renders_yaml() {
+        let expected = "\
+# Generated by dhcpctl from network.yaml
+
+authoritative;
+default-lease-time 600;
+max-lease-time 7200;
+option domain-name \"example.org\";
+option domain-name-servers 10.0.0.2, 10.0.0.3;
+ddns-update-style none;
+
+shared-network vlan10 {
+  subnet 10.0.1.0 netmask 255.255.255.0 {
+    range 10.0.1.100 10.0.1.199;
+    option broadcast-address 10.0.1.255;
+    option routers 10.0.1.1;
+    option subnet-mask 255.255.255.0;
+  }
+  subnet 10.0.2.0 netmask 255.255.254.0 {
+    range 10.0.2.10 10.0.2.250;
+    option bootfile-name \"pxelinux.0\";
+    option broadcast-address 10.0.3.255;
+    option routers 10.0.2.1;
+    option subnet-mask 255.255.254.0;
+    next-server 10.0.0.5;
+  }
+}
+
+subnet 10.0.9.0 netmask 255.255.255.0 {
+  option broadcast-address 10.0.9.255;
+  option routers 10.0.9.1;
+  option subnet-mask 255.255.255.0;
+  pool {
+    deny unknown-clients;
+    range 10.0.9.50 10.0.9.99;
+  }
+}
+
+host printer-3 {
+  hardware ethernet 00:11:22:33:44:55;
+  fixed-address 10.0.1.23;
+  option host-name \"printer-3\";
+}";
+        assert_eq!(render_file("network.yaml", YAML).unwrap(), expected);
+    }
+
+    #[test]
+    fn renders_toml_with_integer_lease_times() {
+        let expected = "\
+# Generated by dhcpctl from network.toml
+
+default-lease-time 600;
+max-lease-time 7200;
+
+subnet 10.0.1.0 netmask 255.255.255.0 {
+  range 10.0.1.100 10.0.1.199;
+  option broadcast-address 10.0.1.255;
+  option routers 10.0.1.1;
+  option subnet-mask 255.255.255.0;
+  max-lease-time 3600;
+}";
+        assert_eq!(render_file("network.toml", TOML).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_maps() {
+        let text = "globals:\n  ddns: { a: 1 }\n";
+        assert!(matches!(
+            render_file("map-global.yaml", text),
+            Err(DhcpctlError::DhcpdConf(_))
+        ));
+        let text = "hosts:\n  - name: a\n    options: { host-name: [{ a: 1 }] }\n";
+        assert!(matches!(
+            render_file("map-host.yaml", text),
+            Err(DhcpctlError::DhcpdConf(_))
+        ));
+    }
+
+    #[test]
+    fn fail_on_stops_rendering() {
+        let text = "scopes:\n  - ip: 10.0.1.0\n    subnet: 255.255.255.0\n    range: { start: 10.0.1.200, end: 10.0.2.10 }\n    options: { routers: 10.0.1.1 }\n";
+        let file = write("bad.yaml", text);
+        let result = print_config(&file, Severity::Error);
+        fs::remove_file(&file).unwrap();
+        assert!(matches!(result, Err(DhcpctlError::ProblemsFound(2))));
+    }
+}
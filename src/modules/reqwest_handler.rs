@@ -1,13 +1,34 @@
+use super::audit;
 use super::config;
-use super::config::ConfyConfig;
+use super::config::Profile;
 use super::error::DhcpctlError;
+use colored::Colorize;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use reqwest;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
 use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// Everything but the unreserved characters of RFC 3986.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+// Encodes a user supplied value, like a host name, for use as one segment of
+// an API path.
+pub fn path_segment(value: &str) -> String {
+    utf8_percent_encode(value, PATH_SEGMENT).to_string()
+}
 
 pub async fn run<T: DeserializeOwned>(path: &str) -> Result<T, DhcpctlError> {
-    let config: ConfyConfig = config::load_config()?;
+    let profile: Profile = config::load_profile()?;
+    run_on(&profile, path).await
+}
 
+fn request(config: &Profile, method: Method, path: &str) -> Result<RequestBuilder, DhcpctlError> {
     if config.api_url.is_empty() {
         return Err(DhcpctlError::MissingUrl);
     }
@@ -19,14 +40,106 @@ pub async fn run<T: DeserializeOwned>(path: &str) -> Result<T, DhcpctlError> {
     }
 
     let client = reqwest::Client::new();
-    let response = client
-        .get(format!("{}{}", config.api_url, path))
-        .headers(headers)
-        .send()
-        .await?;
+    Ok(client
+        .request(method, format!("{}{}", config.api_url, path))
+        .headers(headers))
+}
+
+async fn send(config: &Profile, path: &str) -> Result<Response, DhcpctlError> {
+    Ok(request(config, Method::GET, path)?.send().await?)
+}
+
+// A change that reached the server must not be reported as failed because
+// the audit log couldn't be written, so that only gets a warning.
+fn record_audit(
+    method: &Method,
+    path: &str,
+    body: Option<serde_json::Value>,
+    status: Option<u16>,
+    error: Option<String>,
+) {
+    if let Err(error) = audit::record(method.as_str(), path, body, status, error) {
+        eprintln!("{} {}", "Could not write the audit log:".yellow(), error);
+    }
+}
+
+// Sends a change to the API and records it in the audit log, also when it
+// fails. The body of a refused request is part of the error, since it
+// usually says why.
+pub async fn write<B: Serialize>(
+    method: Method,
+    path: &str,
+    body: Option<&B>,
+) -> Result<(), DhcpctlError> {
+    audit::check_writable()?;
+    let profile: Profile = config::load_profile()?;
+    let body = body.map(serde_json::to_value).transpose()?;
+    let mut builder = request(&profile, method.clone(), path)?;
+    if let Some(body) = &body {
+        builder = builder.json(body);
+    }
+    let response = match builder.send().await {
+        Ok(response) => response,
+        Err(error) => {
+            record_audit(&method, path, body, None, Some(error.to_string()));
+            return Err(DhcpctlError::Reqwest(error));
+        }
+    };
+    let status = response.status();
+    record_audit(&method, path, body, Some(status.as_u16()), None);
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(DhcpctlError::BadStatusCode(format!(
+            "HTTP status {} for {} {} {}",
+            status,
+            method,
+            path,
+            text.trim()
+        )));
+    }
+    Ok(())
+}
 
+pub async fn run_on<T: DeserializeOwned>(config: &Profile, path: &str) -> Result<T, DhcpctlError> {
+    let response = send(config, path).await?;
     match response.error_for_status() {
         Ok(res) => Ok(res.json::<T>().await?),
         Err(error) => Err(DhcpctlError::BadStatusCode(error.to_string())),
     }
 }
+
+// Like `run`, but gives None when the API has no such path, eg. endpoints
+// missing from older API versions. Every other error is returned.
+pub async fn run_optional<T: DeserializeOwned>(path: &str) -> Result<Option<T>, DhcpctlError> {
+    let profile: Profile = config::load_profile()?;
+    let response = send(&profile, path).await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    match response.error_for_status() {
+        Ok(res) => Ok(Some(res.json::<T>().await?)),
+        Err(error) => Err(DhcpctlError::BadStatusCode(error.to_string())),
+    }
+}
+
+// Prints the response body untouched, also for error responses, so schema
+// changes and API errors can be inspected.
+pub async fn print_raw(path: &str) -> Result<(), DhcpctlError> {
+    let profile: Profile = config::load_profile()?;
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+    let response = send(&profile, &path).await?;
+    let status = response.status();
+    println!("{}", response.text().await?);
+
+    if !status.is_success() {
+        return Err(DhcpctlError::BadStatusCode(format!(
+            "HTTP status {} for {}",
+            status, path
+        )));
+    }
+    Ok(())
+}
@@ -1,44 +1,318 @@
 use super::error::DhcpctlError;
+use super::hosts::{self, Host};
+use super::leases::{self, Lease};
 use super::reqwest_handler;
 use colored::Colorize;
 use prettytable::{cell, format, row, Attr, Cell, Row, Table};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
 
+// Like Lease, missing fields default and unknown ones are kept in `extra`.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Scope {
-    ip: String,
-    subnet: String,
-    range: Range,
-    options: Options,
+    pub ip: String,
+    pub subnet: String,
+    #[serde(
+        rename = "range",
+        alias = "ranges",
+        default,
+        deserialize_with = "one_or_many"
+    )]
+    pub ranges: Vec<Range>,
+    #[serde(default)]
+    pub pools: Vec<Pool>,
+    #[serde(default)]
+    pub options: Options,
     #[serde(rename = "next-server")]
-    next_server: Option<String>,
-    #[serde(rename = "default-lease-time")]
-    default_lease_time: Option<String>,
-    #[serde(rename = "max-lease-time")]
-    max_lease_time: Option<String>,
+    pub next_server: Option<String>,
+    #[serde(
+        rename = "default-lease-time",
+        default,
+        deserialize_with = "string_or_number"
+    )]
+    pub default_lease_time: Option<String>,
+    #[serde(
+        rename = "max-lease-time",
+        default,
+        deserialize_with = "string_or_number"
+    )]
+    pub max_lease_time: Option<String>,
+    #[serde(rename = "shared-network")]
+    pub shared_network: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Range {
-    start: String,
-    end: String,
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Range {
+    #[serde(default)]
+    pub start: String,
+    #[serde(default)]
+    pub end: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Options {
-    #[serde(rename = "subnet-mask")]
-    subnet_mask: String,
-    #[serde(rename = "broadcast-address")]
-    broadcast_address: String,
-    routers: String,
+impl Range {
+    pub fn bounds(&self) -> Option<(u32, u32)> {
+        Some((ip_to_u32(&self.start)?, ip_to_u32(&self.end)?))
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} - {}", self.start, self.end)
+    }
+}
+
+// A `pool` block inside the subnet with its own ranges and access rules,
+// eg. `allow known-clients` or `deny unknown-clients`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Pool {
+    #[serde(
+        rename = "range",
+        alias = "ranges",
+        default,
+        deserialize_with = "one_or_many"
+    )]
+    pub ranges: Vec<Range>,
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(Range),
+    Many(Vec<Range>),
+}
+
+// The API sends a single range object for subnets with one `range`
+// statement, a list for several and nothing for reservation-only subnets.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Range>, D::Error> {
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        Some(OneOrMany::One(range)) => vec![range],
+        Some(OneOrMany::Many(ranges)) => ranges,
+        None => Vec::new(),
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrNumber {
+    String(String),
+    Number(serde_json::Number),
+}
+
+// Lease times are strings in the API but plain integers are more natural in
+// YAML and TOML definitions.
+pub fn string_or_number<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Ok(match Option::<StringOrNumber>::deserialize(deserializer)? {
+        Some(StringOrNumber::String(x)) => Some(x),
+        Some(StringOrNumber::Number(x)) => Some(x.to_string()),
+        None => None,
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Options {
+    #[serde(rename = "subnet-mask", default)]
+    pub subnet_mask: String,
+    #[serde(rename = "broadcast-address", default)]
+    pub broadcast_address: String,
+    #[serde(default)]
+    pub routers: String,
     #[serde(rename = "tftp-server-name")]
-    tftp_server_name: Option<String>,
+    pub tftp_server_name: Option<String>,
     #[serde(rename = "bootfile-name")]
-    bootfile_name: Option<String>,
+    pub bootfile_name: Option<String>,
     #[serde(rename = "domain-name")]
-    domain_name: Option<String>,
+    pub domain_name: Option<String>,
     #[serde(rename = "domain-name-servers")]
-    domain_name_servers: Option<Vec<String>>,
+    pub domain_name_servers: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Default)]
+pub struct ScopeUsage {
+    pub size: u32,
+    pub active: u32,
+    pub states: BTreeMap<String, u32>,
+}
+
+#[derive(Debug)]
+pub enum GroupBy {
+    SharedNetwork,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shared-network" => Ok(GroupBy::SharedNetwork),
+            _ => Err(format!("Unknown grouping '{}'.", s)),
+        }
+    }
+}
+
+impl ScopeUsage {
+    pub fn add(&mut self, other: ScopeUsage) {
+        self.size += other.size;
+        self.active += other.active;
+        for (state, count) in other.states {
+            *self.states.entry(state).or_insert(0) += count;
+        }
+    }
+
+    pub fn label(&self) -> String {
+        format!(
+            "{}/{} ({:.0}%)",
+            self.active,
+            self.size,
+            self.utilization() * 100.0
+        )
+    }
+
+    pub fn free(&self) -> u32 {
+        self.size.saturating_sub(self.active)
+    }
+
+    pub fn utilization(&self) -> f64 {
+        if self.size == 0 {
+            return 0.0;
+        }
+        self.active as f64 / self.size as f64
+    }
+}
+
+pub fn ip_to_u32(ip: &str) -> Option<u32> {
+    ip.parse::<Ipv4Addr>().ok().map(u32::from)
+}
+
+// A bare IP is treated as a /32.
+pub fn parse_cidr(value: &str) -> Option<(u32, u32)> {
+    let (ip, prefix) = match value.split_once('/') {
+        Some((ip, prefix)) => (ip, prefix.parse::<u32>().ok().filter(|x| *x <= 32)?),
+        None => (value, 32),
+    };
+    let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+    Some((ip_to_u32(ip)? & mask, mask))
+}
+
+impl Scope {
+    pub fn network(&self) -> Option<(u32, u32)> {
+        let mask = ip_to_u32(&self.subnet)?;
+        Some((ip_to_u32(&self.ip)? & mask, mask))
+    }
+
+    // Two networks overlap when one of them contains the other.
+    pub fn overlaps(&self, network: u32, mask: u32) -> bool {
+        match self.network() {
+            Some((own, own_mask)) => own & mask == network || network & own_mask == own,
+            None => false,
+        }
+    }
+
+    pub fn prefix_len(&self) -> u32 {
+        ip_to_u32(&self.subnet).map(u32::count_ones).unwrap_or(32)
+    }
+
+    pub fn cidr(&self) -> String {
+        format!("{}/{}", self.ip, self.prefix_len())
+    }
+
+    pub fn contains(&self, ip: &str) -> bool {
+        match (ip_to_u32(ip), ip_to_u32(&self.ip), ip_to_u32(&self.subnet)) {
+            (Some(ip), Some(network), Some(mask)) => ip & mask == network & mask,
+            _ => false,
+        }
+    }
+
+    // The subnet ranges followed by the ranges of its pools.
+    pub fn all_ranges(&self) -> Vec<&Range> {
+        self.ranges
+            .iter()
+            .chain(self.pools.iter().flat_map(|x| x.ranges.iter()))
+            .collect()
+    }
+
+    // Valid ranges sorted and merged, so overlapping range statements are
+    // only counted once.
+    pub fn range_intervals(&self) -> Vec<(u32, u32)> {
+        let mut bounds: Vec<(u32, u32)> = self
+            .all_ranges()
+            .iter()
+            .filter_map(|x| x.bounds())
+            .filter(|(start, end)| start <= end)
+            .collect();
+        bounds.sort_unstable();
+
+        let mut merged: Vec<(u32, u32)> = Vec::new();
+        for (start, end) in bounds {
+            match merged.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        merged
+    }
+
+    pub fn range_contains(&self, ip: &str) -> bool {
+        match ip_to_u32(ip) {
+            Some(ip) => self
+                .range_intervals()
+                .iter()
+                .any(|(start, end)| ip >= *start && ip <= *end),
+            None => false,
+        }
+    }
+
+    pub fn range_size(&self) -> u32 {
+        self.range_intervals()
+            .iter()
+            .map(|(start, end)| end - start + 1)
+            .sum()
+    }
+
+    pub fn range_label(&self) -> String {
+        let ranges = self.all_ranges();
+        if ranges.is_empty() {
+            return "none".to_string();
+        }
+        ranges
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    // Leases are counted against the scope by subnet, but only active leases
+    // inside the dynamic range count towards utilization.
+    pub fn usage(&self, leases: &[Lease]) -> ScopeUsage {
+        let mut usage = ScopeUsage {
+            size: self.range_size(),
+            active: 0,
+            states: BTreeMap::new(),
+        };
+
+        for lease in leases.iter().filter(|x| self.contains(&x.ip)) {
+            *usage.states.entry(lease.binding_state.clone()).or_insert(0) += 1;
+            if lease.binding_state == "active" && self.range_contains(&lease.ip) {
+                usage.active += 1;
+            }
+        }
+        usage
+    }
 }
 
 fn table_format() -> format::TableFormat {
@@ -52,13 +326,68 @@ fn table_format() -> format::TableFormat {
         .build()
 }
 
-pub async fn list_scopes(pxe: bool, dns: bool) -> Result<(), DhcpctlError> {
-    let payload: Vec<Scope> = reqwest_handler::run("/config/scopes").await?;
+// Subnets in a shared network serve clients from one combined pool, so the
+// usage is summed per shared network. Subnets outside one get their own row.
+fn print_shared_networks(scopes: &[Scope], leases: &[Lease]) {
+    let mut groups: BTreeMap<(bool, String), Vec<&Scope>> = BTreeMap::new();
+    for scope in scopes.iter() {
+        let key = match &scope.shared_network {
+            Some(name) => (false, name.clone()),
+            None => (true, scope.cidr()),
+        };
+        groups.entry(key).or_default().push(scope);
+    }
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!(b -> "Shared Network", b -> "Subnets", b -> "Ranges", b -> "Usage"));
+    for ((standalone, _), members) in groups.iter() {
+        let mut usage = ScopeUsage::default();
+        for scope in members.iter() {
+            usage.add(scope.usage(leases));
+        }
+        let name = if *standalone {
+            "(none)".to_string()
+        } else {
+            members[0].shared_network.clone().unwrap_or_default()
+        };
+        table.add_row(row!(
+            name,
+            members
+                .iter()
+                .map(|x| x.cidr())
+                .collect::<Vec<String>>()
+                .join("\n"),
+            members
+                .iter()
+                .map(|x| x.range_label())
+                .collect::<Vec<String>>()
+                .join("\n"),
+            usage.label(),
+        ));
+    }
+    table.printstd();
+}
+
+pub async fn list_scopes(
+    pxe: bool,
+    dns: bool,
+    group_by: Option<GroupBy>,
+) -> Result<(), DhcpctlError> {
+    let (payload, leases): (Vec<Scope>, Vec<Lease>) = tokio::try_join!(
+        reqwest_handler::run("/config/scopes"),
+        reqwest_handler::run("/leases/")
+    )?;
+
+    if let Some(GroupBy::SharedNetwork) = group_by {
+        print_shared_networks(&payload, &leases);
+        return Ok(());
+    }
 
     let mut table = Table::new();
     table.set_format(table_format());
 
-    let mut row: Row = row!(b -> "Subnet ID", b -> "Subnet Mask", b -> "Scope Start", b -> "Scope End", b -> "Gateway");
+    let mut row: Row = row!(b -> "Subnet ID", b -> "Subnet Mask", b -> "Scope Start", b -> "Scope End", b -> "Gateway", b -> "Usage");
 
     if dns {
         row.add_cell(Cell::new("DNS Servers").with_style(Attr::Bold));
@@ -73,12 +402,23 @@ pub async fn list_scopes(pxe: bool, dns: bool) -> Result<(), DhcpctlError> {
     table.set_titles(row);
 
     for scope in payload.iter() {
+        let usage = scope.usage(&leases);
+        let ranges = scope.all_ranges();
         let mut row: Row = row!(
             &scope.ip,
             &scope.subnet,
-            &scope.range.start,
-            &scope.range.end,
+            ranges
+                .iter()
+                .map(|x| x.start.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n"),
+            ranges
+                .iter()
+                .map(|x| x.end.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n"),
             &scope.options.routers,
+            usage.label(),
         );
 
         if dns {
@@ -109,22 +449,49 @@ pub async fn list_scopes(pxe: bool, dns: bool) -> Result<(), DhcpctlError> {
 
 pub async fn get_scope(subnet_id: &str) -> Result<(), DhcpctlError> {
     let mut payload: Vec<Scope> = reqwest_handler::run("/config/scopes").await?;
+    let reservations = hosts::fetch_reservations().await?;
 
-    payload.retain(|f| f.ip == subnet_id);
+    let (network, mask) = parse_cidr(subnet_id).ok_or(DhcpctlError::NotValidCIDR)?;
+    payload.retain(|f| f.overlaps(network, mask));
 
     if payload.len() == 0 {
-        println!("No subnet found by that ID");
+        println!("No subnet found containing {}", subnet_id);
     }
 
-    for scope in payload.iter() {
+    for (index, scope) in payload.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
         println!("{} {}", "Network ID:".bold(), &scope.ip);
         println!("{} {}", "Subnet Mask:".bold(), &scope.subnet);
-        println!(
-            "{} {} - {}",
-            "Scope Range:".bold(),
-            &scope.range.start,
-            &scope.range.end
-        );
+        if let Some(shared_network) = &scope.shared_network {
+            println!("{} {}", "Shared Network:".bold(), shared_network);
+        }
+        if scope.ranges.is_empty() {
+            println!("{} none", "Scope Range:".bold());
+        }
+        for range in scope.ranges.iter() {
+            println!("{} {}", "Scope Range:".bold(), range);
+        }
+        for pool in scope.pools.iter() {
+            let ranges: Vec<String> = pool.ranges.iter().map(|x| x.to_string()).collect();
+            let rules: Vec<String> = pool
+                .allow
+                .iter()
+                .map(|x| format!("allow {}", x))
+                .chain(pool.deny.iter().map(|x| format!("deny {}", x)))
+                .collect();
+            println!(
+                "{} {} {}",
+                "Pool:".bold(),
+                ranges.join(", "),
+                if rules.is_empty() {
+                    String::new()
+                } else {
+                    format!("({})", rules.join(", "))
+                }
+            );
+        }
         println!("{} {}", "Gateway:".bold(), &scope.options.routers);
 
         if scope.options.tftp_server_name.is_some()
@@ -185,7 +552,252 @@ pub async fn get_scope(subnet_id: &str) -> Result<(), DhcpctlError> {
                 scope.max_lease_time.clone().unwrap()
             );
         }
+
+        let reserved: Vec<&Host> = reservations
+            .iter()
+            .filter(|x| scope.contains(&x.fixed_address))
+            .collect();
+        if !reserved.is_empty() {
+            println!();
+        }
+        for host in reserved {
+            println!(
+                "{} {} {} {}",
+                "Reservation:".bold(),
+                host.fixed_address,
+                host.hardware_ethernet,
+                host.name
+            );
+        }
+
+        if !scope.extra.is_empty() || !scope.options.extra.is_empty() {
+            println!();
+            leases::print_extra_fields(&scope.extra);
+            leases::print_extra_fields(&scope.options.extra);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn which_scopes(ips: &[String]) -> Result<(), DhcpctlError> {
+    let payload: Vec<Scope> = reqwest_handler::run("/config/scopes").await?;
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(
+        row!(b -> "IP", b -> "Subnet ID", b -> "Subnet Mask", b -> "In Range", b -> "Gateway"),
+    );
+
+    for ip in ips.iter() {
+        if ip_to_u32(ip).is_none() {
+            return Err(DhcpctlError::NotValidCIDR);
+        }
+        let matched: Vec<&Scope> = payload.iter().filter(|x| x.contains(ip)).collect();
+        if matched.is_empty() {
+            table.add_row(row!(ip, Fy -> "No scope", "", "", ""));
+        }
+        for scope in matched {
+            table.add_row(row!(
+                ip,
+                &scope.ip,
+                &scope.subnet,
+                if scope.range_contains(ip) {
+                    "yes"
+                } else {
+                    "no"
+                },
+                &scope.options.routers,
+            ));
+        }
+    }
+    table.printstd();
+    Ok(())
+}
+
+// Addresses that can't be handed out statically as sorted, merged intervals:
+// network, broadcast, gateways, everything with a lease in any state, reserved
+// addresses and the dynamic range, which `hosts add` refuses.
+fn used_intervals(scope: &Scope, leases: &[Lease], reservations: &[Host]) -> Vec<(u32, u32)> {
+    let mut used: Vec<(u32, u32)> = scope.range_intervals();
+    if let Some((network, mask)) = scope.network() {
+        used.push((network, network));
+        used.push((network | !mask, network | !mask));
+    }
+    let singles = scope
+        .options
+        .routers
+        .split(',')
+        .map(str::trim)
+        .chain(std::iter::once(scope.options.broadcast_address.as_str()))
+        .chain(leases.iter().map(|x| x.ip.as_str()))
+        .chain(reservations.iter().map(|x| x.fixed_address.as_str()))
+        .filter(|x| scope.contains(x))
+        .filter_map(ip_to_u32);
+    used.extend(singles.map(|ip| (ip, ip)));
+    used.sort_unstable();
+
+    let mut merged: Vec<(u32, u32)> = Vec::new();
+    for (start, end) in used {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// The gaps between the used intervals. With a `limit` it stops once that many
+// addresses are found, so large subnets are never walked address by address.
+fn free_blocks(scope: &Scope, used: &[(u32, u32)], limit: Option<usize>) -> Vec<(u32, u32)> {
+    let mut blocks: Vec<(u32, u32)> = Vec::new();
+    let (network, mask) = match scope.network() {
+        Some(x) => x,
+        None => return blocks,
+    };
+    let last = u64::from(network | !mask);
+    let mut left = limit.map(|x| x as u64).unwrap_or(u64::MAX);
+
+    let mut next = u64::from(network);
+    let gaps = used
+        .iter()
+        .map(|(start, end)| (u64::from(*start), u64::from(*end)))
+        .chain(std::iter::once((last + 1, last + 1)));
+    for (start, end) in gaps {
+        if left == 0 || next > last {
+            break;
+        }
+        if start > next {
+            let gap_end = (start - 1).min(last).min(next.saturating_add(left - 1));
+            blocks.push((next as u32, gap_end as u32));
+            left -= gap_end - next + 1;
+        }
+        next = next.max(end + 1);
     }
+    blocks
+}
+
+pub async fn free_addresses(subnet_id: &str, count: Option<usize>) -> Result<(), DhcpctlError> {
+    let (payload, leases): (Vec<Scope>, Vec<Lease>) = tokio::try_join!(
+        reqwest_handler::run("/config/scopes"),
+        reqwest_handler::run("/leases/")
+    )?;
+
+    let reservations = hosts::fetch_reservations().await?;
+
+    let (network, mask) = parse_cidr(subnet_id).ok_or(DhcpctlError::NotValidCIDR)?;
+    let matched: Vec<&Scope> = payload
+        .iter()
+        .filter(|x| x.overlaps(network, mask))
+        .collect();
+
+    if matched.is_empty() {
+        println!("No subnet found containing {}", subnet_id);
+    }
+
+    // Plain output, one address or block per line, so it can be piped.
+    for scope in matched {
+        let used = used_intervals(scope, &leases, &reservations);
+        let blocks = free_blocks(scope, &used, count);
 
+        match count {
+            Some(_) => {
+                for ip in blocks.iter().flat_map(|(start, end)| *start..=*end) {
+                    println!("{}", Ipv4Addr::from(ip));
+                }
+            }
+            None => {
+                for (start, end) in blocks.iter() {
+                    if start == end {
+                        println!("{}", Ipv4Addr::from(*start));
+                    } else {
+                        println!("{}-{}", Ipv4Addr::from(*start), Ipv4Addr::from(*end));
+                    }
+                }
+            }
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scope(ip: &str, subnet: &str) -> Scope {
+        serde_json::from_value(json!({
+            "ip": ip,
+            "subnet": subnet,
+            "range": {"start": "10.0.1.100", "end": "10.0.1.199"},
+            "options": {"routers": "10.0.1.1", "broadcast-address": "10.0.1.255"}
+        }))
+        .unwrap()
+    }
+
+    fn lease(ip: &str) -> Lease {
+        serde_json::from_value(json!({"ip": ip, "hardware-ethernet": "00:1a:2b:3c:4d:5e"})).unwrap()
+    }
+
+    fn host(ip: &str) -> Host {
+        Host {
+            name: "printer-1".to_string(),
+            fixed_address: ip.to_string(),
+            ..Host::default()
+        }
+    }
+
+    fn text(blocks: &[(u32, u32)]) -> Vec<String> {
+        blocks
+            .iter()
+            .map(|(start, end)| format!("{}-{}", Ipv4Addr::from(*start), Ipv4Addr::from(*end)))
+            .collect()
+    }
+
+    #[test]
+    fn free_blocks_skip_used_addresses() {
+        let scope = scope("10.0.1.0", "255.255.255.0");
+        let leases = vec![lease("10.0.1.10"), lease("10.0.1.150"), lease("10.0.2.5")];
+        let hosts = vec![host("10.0.1.20"), host("10.0.1.21"), host("printer.lan")];
+        let used = used_intervals(&scope, &leases, &hosts);
+        assert_eq!(
+            text(&free_blocks(&scope, &used, None)),
+            vec![
+                "10.0.1.2-10.0.1.9",
+                "10.0.1.11-10.0.1.19",
+                "10.0.1.22-10.0.1.99",
+                "10.0.1.200-10.0.1.254",
+            ]
+        );
+    }
+
+    #[test]
+    fn free_blocks_stop_at_count() {
+        let scope = scope("10.0.1.0", "255.255.255.0");
+        let used = used_intervals(&scope, &[lease("10.0.1.4")], &[]);
+        assert_eq!(
+            text(&free_blocks(&scope, &used, Some(3))),
+            vec!["10.0.1.2-10.0.1.3", "10.0.1.5-10.0.1.5"]
+        );
+        assert!(free_blocks(&scope, &used, Some(0)).is_empty());
+    }
+
+    #[test]
+    fn free_blocks_of_huge_subnets() {
+        let scope = scope("0.0.0.0", "0.0.0.0");
+        let used = used_intervals(&scope, &[], &[]);
+        assert_eq!(
+            text(&free_blocks(&scope, &used, None)),
+            vec![
+                "0.0.0.1-10.0.1.0",
+                "10.0.1.2-10.0.1.99",
+                "10.0.1.200-10.0.1.254",
+                "10.0.2.0-255.255.255.254",
+            ]
+        );
+        assert_eq!(
+            text(&free_blocks(&scope, &used, Some(2))),
+            vec!["0.0.0.1-0.0.0.2"]
+        );
+    }
+}
@@ -0,0 +1,698 @@
+use super::error::DhcpctlError;
+use super::lease_time::LeaseTime;
+use super::leases::{self, Lease};
+use super::reqwest_handler;
+use super::scopes::Scope;
+use chrono::prelude::*;
+use colored::Colorize;
+use prettytable::{cell, format, row, Table};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum StatsFormat {
+    Table,
+    Json,
+    Influx,
+    PromTextfile,
+}
+
+impl FromStr for StatsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(StatsFormat::Table),
+            "json" => Ok(StatsFormat::Json),
+            "influx" => Ok(StatsFormat::Influx),
+            "prom-textfile" => Ok(StatsFormat::PromTextfile),
+            _ => Err(format!("Unknown format '{}'.", s)),
+        }
+    }
+}
+
+struct ScopeMetrics {
+    subnet: String,
+    size: u32,
+    active: u32,
+    free: u32,
+    utilization: f64,
+    states: BTreeMap<String, u32>,
+}
+
+struct Metrics {
+    scopes: Vec<ScopeMetrics>,
+    vendors: BTreeMap<String, u32>,
+}
+
+fn collect_metrics(scopes: &[Scope], leases: &[Lease]) -> Metrics {
+    let scopes = scopes
+        .iter()
+        .map(|scope| {
+            let usage = scope.usage(leases);
+            ScopeMetrics {
+                subnet: scope.cidr(),
+                size: usage.size,
+                active: usage.active,
+                free: usage.free(),
+                utilization: usage.utilization(),
+                states: usage.states,
+            }
+        })
+        .collect();
+
+    let mut vendors: BTreeMap<String, u32> = BTreeMap::new();
+    for lease in leases.iter().filter(|x| x.binding_state == "active") {
+        let vendor = lease
+            .set_vendor_class_identifier
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string());
+        *vendors.entry(vendor).or_insert(0) += 1;
+    }
+
+    Metrics { scopes, vendors }
+}
+
+fn escape_influx_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+fn escape_prom_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn render_influx(metrics: &Metrics) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_nanos())
+        .unwrap_or_default();
+    let mut out = String::new();
+
+    for scope in metrics.scopes.iter() {
+        let subnet = escape_influx_tag(&scope.subnet);
+        out.push_str(&format!(
+            "dhcp_scope,subnet={} size={}i,active={}i,free={}i,utilization={} {}\n",
+            subnet, scope.size, scope.active, scope.free, scope.utilization, timestamp
+        ));
+        for (state, count) in scope.states.iter() {
+            out.push_str(&format!(
+                "dhcp_scope_leases,subnet={},state={} count={}i {}\n",
+                subnet,
+                escape_influx_tag(state),
+                count,
+                timestamp
+            ));
+        }
+    }
+
+    for (vendor, count) in metrics.vendors.iter() {
+        out.push_str(&format!(
+            "dhcp_vendor_leases,vendor={} count={}i {}\n",
+            escape_influx_tag(vendor),
+            count,
+            timestamp
+        ));
+    }
+    out
+}
+
+fn push_gauge(out: &mut String, name: &str, help: &str, samples: Vec<(String, String)>) {
+    out.push_str(&format!(
+        "# HELP {} {}\n# TYPE {} gauge\n",
+        name, help, name
+    ));
+    for (labels, value) in samples {
+        out.push_str(&format!("{}{{{}}} {}\n", name, labels, value));
+    }
+}
+
+fn render_prom_textfile(metrics: &Metrics) -> String {
+    let mut out = String::new();
+    let subnet_label = |x: &ScopeMetrics| format!("subnet=\"{}\"", escape_prom_label(&x.subnet));
+
+    push_gauge(
+        &mut out,
+        "dhcp_scope_size",
+        "Number of addresses in the dynamic range.",
+        metrics
+            .scopes
+            .iter()
+            .map(|x| (subnet_label(x), x.size.to_string()))
+            .collect(),
+    );
+    push_gauge(
+        &mut out,
+        "dhcp_scope_active_leases",
+        "Active leases inside the dynamic range.",
+        metrics
+            .scopes
+            .iter()
+            .map(|x| (subnet_label(x), x.active.to_string()))
+            .collect(),
+    );
+    push_gauge(
+        &mut out,
+        "dhcp_scope_free_addresses",
+        "Unleased addresses in the dynamic range.",
+        metrics
+            .scopes
+            .iter()
+            .map(|x| (subnet_label(x), x.free.to_string()))
+            .collect(),
+    );
+    push_gauge(
+        &mut out,
+        "dhcp_scope_utilization_ratio",
+        "Active leases divided by range size.",
+        metrics
+            .scopes
+            .iter()
+            .map(|x| (subnet_label(x), x.utilization.to_string()))
+            .collect(),
+    );
+    push_gauge(
+        &mut out,
+        "dhcp_scope_leases",
+        "Leases in the subnet by binding state.",
+        metrics
+            .scopes
+            .iter()
+            .flat_map(|x| {
+                x.states.iter().map(move |(state, count)| {
+                    (
+                        format!("{},state=\"{}\"", subnet_label(x), escape_prom_label(state)),
+                        count.to_string(),
+                    )
+                })
+            })
+            .collect(),
+    );
+    push_gauge(
+        &mut out,
+        "dhcp_vendor_leases",
+        "Active leases by vendor class identifier.",
+        metrics
+            .vendors
+            .iter()
+            .map(|(vendor, count)| {
+                (
+                    format!("vendor=\"{}\"", escape_prom_label(vendor)),
+                    count.to_string(),
+                )
+            })
+            .collect(),
+    );
+    out
+}
+
+#[derive(Serialize)]
+struct Count {
+    name: String,
+    count: u32,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    total: usize,
+    active: usize,
+    #[serde(rename = "by-state")]
+    by_state: Vec<Count>,
+    #[serde(rename = "by-scope")]
+    by_scope: Vec<Count>,
+    #[serde(rename = "by-vendor-class")]
+    by_vendor_class: Vec<Count>,
+    #[serde(rename = "by-oui")]
+    by_oui: Vec<Count>,
+    #[serde(rename = "by-mac-type")]
+    by_mac_type: Vec<Count>,
+    #[serde(rename = "lease-age")]
+    lease_age: Vec<Count>,
+    #[serde(rename = "time-to-expiry")]
+    time_to_expiry: Vec<Count>,
+}
+
+const AGE_BUCKETS: [(&str, i64); 4] = [
+    ("< 1h", 3600),
+    ("1h - 1d", 86400),
+    ("1d - 7d", 604800),
+    ("> 7d", i64::MAX),
+];
+
+const EXPIRY_BUCKETS: [(&str, i64); 5] = [
+    ("expired", 0),
+    ("< 15m", 900),
+    ("15m - 1h", 3600),
+    ("1h - 1d", 86400),
+    ("> 1d", i64::MAX),
+];
+
+// Sorted by count, anything after the first `top` entries is summed up
+// in an "(other)" entry.
+fn top_counts(counts: HashMap<String, u32>, top: usize) -> Vec<Count> {
+    let mut counts: Vec<Count> = counts
+        .into_iter()
+        .map(|(name, count)| Count { name, count })
+        .collect();
+    counts.sort_by_key(|x| (Reverse(x.count), x.name.clone()));
+
+    if counts.len() > top {
+        let other: u32 = counts.drain(top..).map(|x| x.count).sum();
+        counts.push(Count {
+            name: "(other)".to_string(),
+            count: other,
+        });
+    }
+    counts
+}
+
+// Seconds are put in the first bucket they are below. Values without
+// seconds, eg. infinite or unparsable lease times, are counted under the
+// given label after the buckets.
+fn bucket_counts(values: Vec<Result<i64, &str>>, buckets: &[(&str, i64)]) -> Vec<Count> {
+    let mut counts: Vec<Count> = buckets
+        .iter()
+        .map(|(name, _)| Count {
+            name: name.to_string(),
+            count: 0,
+        })
+        .collect();
+    let mut other: BTreeMap<&str, u32> = BTreeMap::new();
+
+    for value in values {
+        match value {
+            Ok(x) => match buckets.iter().position(|(_, limit)| x < *limit) {
+                Some(index) => counts[index].count += 1,
+                None => *other.entry("unknown").or_insert(0) += 1,
+            },
+            Err(label) => *other.entry(label).or_insert(0) += 1,
+        }
+    }
+    counts.extend(other.into_iter().map(|(name, count)| Count {
+        name: name.to_string(),
+        count,
+    }));
+    counts
+}
+
+fn seconds_since(now: DateTime<Utc>, timestamp: &str) -> Result<i64, &'static str> {
+    match LeaseTime::parse(timestamp) {
+        LeaseTime::At(x) => Ok((now - x).num_seconds()),
+        LeaseTime::Never => Err("never"),
+        LeaseTime::Invalid(_) => Err("unknown"),
+    }
+}
+
+fn summarize(
+    scopes: &[Scope],
+    leases: &[Lease],
+    vendors: &HashMap<String, String>,
+    top: usize,
+) -> Summary {
+    let now = Utc::now();
+    let active: Vec<&Lease> = leases
+        .iter()
+        .filter(|x| x.binding_state == "active")
+        .collect();
+
+    let mut by_state: HashMap<String, u32> = HashMap::new();
+    for lease in leases.iter() {
+        *by_state.entry(lease.binding_state.clone()).or_insert(0) += 1;
+    }
+
+    let mut by_scope: HashMap<String, u32> = HashMap::new();
+    let mut by_vendor_class: HashMap<String, u32> = HashMap::new();
+    let mut by_oui: HashMap<String, u32> = HashMap::new();
+    let mut by_mac_type: HashMap<String, u32> = HashMap::new();
+    for lease in active.iter() {
+        let scope = scopes
+            .iter()
+            .find(|x| x.contains(&lease.ip))
+            .map(|x| x.cidr())
+            .unwrap_or_else(|| "(no scope)".to_string());
+        *by_scope.entry(scope).or_insert(0) += 1;
+
+        let vendor_class = lease
+            .set_vendor_class_identifier
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string());
+        *by_vendor_class.entry(vendor_class).or_insert(0) += 1;
+
+        let oui = lease
+            .hardware_ethernet
+            .get(0..8)
+            .unwrap_or_default()
+            .to_lowercase();
+        let oui = vendors.get(&oui).cloned().unwrap_or(oui);
+        *by_oui.entry(oui).or_insert(0) += 1;
+
+        let mac_type = leases::mac_type(&lease.hardware_ethernet).to_string();
+        *by_mac_type.entry(mac_type).or_insert(0) += 1;
+    }
+
+    Summary {
+        total: leases.len(),
+        active: active.len(),
+        by_state: top_counts(by_state, top),
+        by_scope: top_counts(by_scope, top),
+        by_vendor_class: top_counts(by_vendor_class, top),
+        by_oui: top_counts(by_oui, top),
+        by_mac_type: top_counts(by_mac_type, top),
+        lease_age: bucket_counts(
+            active
+                .iter()
+                .map(|x| seconds_since(now, &x.starts))
+                .collect(),
+            &AGE_BUCKETS,
+        ),
+        time_to_expiry: bucket_counts(
+            active
+                .iter()
+                .map(|x| seconds_since(now, &x.ends).map(|x| -x))
+                .collect(),
+            &EXPIRY_BUCKETS,
+        ),
+    }
+}
+
+fn table_format() -> format::TableFormat {
+    format::FormatBuilder::new()
+        .column_separator(' ')
+        .separator(
+            format::LinePosition::Title,
+            format::LineSeparator::new('-', ' ', ' ', ' '),
+        )
+        .padding(1, 1)
+        .build()
+}
+
+fn render_table(summary: &Summary) -> String {
+    let mut out = format!(
+        "{} {}\n{} {}\n",
+        "Total Leases:".bold(),
+        summary.total,
+        "Active Leases:".bold(),
+        summary.active
+    );
+    let sections = [
+        ("By State", &summary.by_state),
+        ("Active By Scope", &summary.by_scope),
+        ("Active By Vendor Class", &summary.by_vendor_class),
+        ("Active By OUI", &summary.by_oui),
+        ("Active By MAC Type", &summary.by_mac_type),
+        ("Active Lease Age", &summary.lease_age),
+        ("Active Time To Expiry", &summary.time_to_expiry),
+    ];
+
+    for (title, counts) in sections.iter() {
+        let mut table = Table::new();
+        table.set_format(table_format());
+        table.set_titles(row!(b -> "Name", b -> "Leases"));
+        for count in counts.iter() {
+            table.add_row(row!(&count.name, r -> count.count));
+        }
+        out.push_str(&format!("\n{}\n{}", title.underline().bold(), table));
+    }
+    out
+}
+
+// Write to a temporary file next to the target and rename it into place, so
+// collectors like the node_exporter textfile collector never read a partial file.
+fn write_atomic(path: &Path, content: &str) -> Result<(), DhcpctlError> {
+    let file_name = path
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    fs::write(&tmp, content)?;
+    if let Err(error) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(DhcpctlError::Io(error));
+    }
+    Ok(())
+}
+
+pub struct StatsOptions {
+    pub format: StatsFormat,
+    pub write: Option<PathBuf>,
+    pub top: usize,
+    pub mac_lookup: bool,
+}
+
+pub async fn print_stats(options: StatsOptions) -> Result<(), DhcpctlError> {
+    let (scopes, leases): (Vec<Scope>, Vec<Lease>) = tokio::try_join!(
+        reqwest_handler::run("/config/scopes"),
+        reqwest_handler::run("/leases/")
+    )?;
+
+    let mut vendors = HashMap::new();
+    if options.mac_lookup {
+        let macs: HashSet<&str> = leases
+            .iter()
+            .filter_map(|x| x.hardware_ethernet.get(0..8))
+            .collect();
+        vendors = leases::get_vendors(&macs).await?;
+    }
+
+    let output = match options.format {
+        StatsFormat::Table => render_table(&summarize(&scopes, &leases, &vendors, options.top)),
+        StatsFormat::Json => {
+            let summary = summarize(&scopes, &leases, &vendors, options.top);
+            format!("{}\n", serde_json::to_string_pretty(&summary)?)
+        }
+        StatsFormat::Influx => render_influx(&collect_metrics(&scopes, &leases)),
+        StatsFormat::PromTextfile => render_prom_textfile(&collect_metrics(&scopes, &leases)),
+    };
+
+    match options.write {
+        Some(path) => write_atomic(&path, &output)?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics() -> Metrics {
+        let mut states = BTreeMap::new();
+        states.insert("active".to_string(), 2);
+        states.insert("free".to_string(), 98);
+        let mut vendors = BTreeMap::new();
+        vendors.insert("MSFT 5.0".to_string(), 1);
+        vendors.insert("a=b,c \"d\"\\".to_string(), 1);
+        Metrics {
+            scopes: vec![ScopeMetrics {
+                subnet: "10.0.1.0/24".to_string(),
+                size: 100,
+                active: 2,
+                free: 98,
+                utilization: 0.02,
+                states,
+            }],
+            vendors,
+        }
+    }
+
+    #[test]
+    fn influx_tags_are_escaped() {
+        assert_eq!(escape_influx_tag("MSFT 5.0"), "MSFT\\ 5.0");
+        assert_eq!(escape_influx_tag("a=b,c d"), "a\\=b\\,c\\ d");
+        assert_eq!(escape_influx_tag("back\\slash"), "back\\\\slash");
+    }
+
+    #[test]
+    fn influx_lines() {
+        let lines: Vec<String> = render_influx(&metrics())
+            .lines()
+            .map(|x| x.rsplit_once(' ').unwrap().0.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "dhcp_scope,subnet=10.0.1.0/24 size=100i,active=2i,free=98i,utilization=0.02",
+                "dhcp_scope_leases,subnet=10.0.1.0/24,state=active count=2i",
+                "dhcp_scope_leases,subnet=10.0.1.0/24,state=free count=98i",
+                "dhcp_vendor_leases,vendor=MSFT\\ 5.0 count=1i",
+                "dhcp_vendor_leases,vendor=a\\=b\\,c\\ \"d\"\\\\ count=1i",
+            ]
+        );
+    }
+
+    #[test]
+    fn prom_labels_are_escaped() {
+        assert_eq!(escape_prom_label("MSFT 5.0"), "MSFT 5.0");
+        assert_eq!(
+            escape_prom_label("say \"hi\"\\\nbye"),
+            "say \\\"hi\\\"\\\\\\nbye"
+        );
+    }
+
+    #[test]
+    fn prom_textfile() {
+        let text = render_prom_textfile(&metrics());
+        assert!(text.starts_with(
+            "# HELP dhcp_scope_size Number of addresses in the dynamic range.\n\
+             # TYPE dhcp_scope_size gauge\n\
+             dhcp_scope_size{subnet=\"10.0.1.0/24\"} 100\n"
+        ));
+        assert!(text.contains("dhcp_scope_utilization_ratio{subnet=\"10.0.1.0/24\"} 0.02\n"));
+        assert!(text.contains("dhcp_scope_leases{subnet=\"10.0.1.0/24\",state=\"free\"} 98\n"));
+        assert!(text.contains("dhcp_vendor_leases{vendor=\"a=b,c \\\"d\\\"\\\\\"} 1\n"));
+    }
+
+    #[test]
+    fn atomic_write_replaces_the_file() {
+        let dir = std::env::temp_dir().join(format!("dhcpctl-stats-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dhcp.prom");
+
+        write_atomic(&path, "first\n").unwrap();
+        write_atomic(&path, "second\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+        let names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|x| x.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["dhcp.prom"]);
+
+        assert!(write_atomic(&dir.join("missing").join("dhcp.prom"), "x").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn counts(counts: &[Count]) -> Vec<(&str, u32)> {
+        counts.iter().map(|x| (x.name.as_str(), x.count)).collect()
+    }
+
+    #[test]
+    fn top_counts_sum_up_the_rest() {
+        let mut input = HashMap::new();
+        input.insert("a".to_string(), 5);
+        input.insert("b".to_string(), 3);
+        input.insert("c".to_string(), 3);
+        input.insert("d".to_string(), 1);
+        assert_eq!(
+            counts(&top_counts(input.clone(), 2)),
+            vec![("a", 5), ("b", 3), ("(other)", 4)]
+        );
+        assert_eq!(
+            counts(&top_counts(input, 4)),
+            vec![("a", 5), ("b", 3), ("c", 3), ("d", 1)]
+        );
+    }
+
+    #[test]
+    fn bucket_limits() {
+        let values = vec![
+            Ok(0),
+            Ok(3599),
+            Ok(3600),
+            Ok(86400),
+            Ok(i64::MAX),
+            Err("never"),
+        ];
+        assert_eq!(
+            counts(&bucket_counts(values, &AGE_BUCKETS)),
+            vec![
+                ("< 1h", 2),
+                ("1h - 1d", 1),
+                ("1d - 7d", 1),
+                ("> 7d", 0),
+                ("never", 1),
+                ("unknown", 1),
+            ]
+        );
+        let values = vec![Ok(-10), Ok(0), Ok(899), Ok(900), Ok(90000), Err("unknown")];
+        assert_eq!(
+            counts(&bucket_counts(values, &EXPIRY_BUCKETS)),
+            vec![
+                ("expired", 1),
+                ("< 15m", 2),
+                ("15m - 1h", 1),
+                ("1h - 1d", 0),
+                ("> 1d", 1),
+                ("unknown", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn summary_of_active_leases() {
+        let at = |minutes: i64| (Utc::now() + chrono::Duration::minutes(minutes)).to_rfc3339();
+        let lease = |ip: &str, mac: &str, state: &str, starts: String, ends: String| -> Lease {
+            serde_json::from_value(serde_json::json!({
+                "ip": ip,
+                "hardware-ethernet": mac,
+                "binding-state": state,
+                "starts": starts,
+                "ends": ends,
+                "set-vendor-class-identifier": "MSFT 5.0"
+            }))
+            .unwrap()
+        };
+        let leases = vec![
+            lease("10.0.1.120", "00:1A:2B:3c:4d:5e", "active", at(-30), at(10)),
+            lease(
+                "10.0.1.121",
+                "da:a1:19:00:11:22",
+                "active",
+                at(-3000),
+                "never".to_string(),
+            ),
+            lease("10.0.9.5", "00:1a:2b:00:00:01", "active", at(-120), at(-1)),
+            lease(
+                "10.0.1.110",
+                "00:1a:2b:3c:4d:5e",
+                "free",
+                at(-9000),
+                at(-8000),
+            ),
+        ];
+        let scopes: Vec<Scope> = serde_json::from_value(serde_json::json!([
+            {"ip": "10.0.1.0", "subnet": "255.255.255.0"}
+        ]))
+        .unwrap();
+        let mut vendors = HashMap::new();
+        vendors.insert("00:1a:2b".to_string(), "Ayecom".to_string());
+
+        let summary = summarize(&scopes, &leases, &vendors, 1);
+        assert_eq!((summary.total, summary.active), (4, 3));
+        assert_eq!(
+            counts(&summary.by_state),
+            vec![("active", 3), ("(other)", 1)]
+        );
+        assert_eq!(
+            counts(&summary.by_scope),
+            vec![("10.0.1.0/24", 2), ("(other)", 1)]
+        );
+        assert_eq!(counts(&summary.by_oui), vec![("Ayecom", 2), ("(other)", 1)]);
+        assert_eq!(
+            counts(&summary.lease_age),
+            vec![("< 1h", 1), ("1h - 1d", 1), ("1d - 7d", 1), ("> 7d", 0)]
+        );
+        assert_eq!(
+            counts(&summary.time_to_expiry),
+            vec![
+                ("expired", 1),
+                ("< 15m", 1),
+                ("15m - 1h", 0),
+                ("1h - 1d", 0),
+                ("> 1d", 0),
+                ("never", 1),
+            ]
+        );
+    }
+}
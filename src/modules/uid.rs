@@ -0,0 +1,302 @@
+use chrono::prelude::*;
+use serde::Serialize;
+
+// The lease `uid` is the DHCP client identifier (option 61). dhcpd writes it
+// either as a quoted string with non-printable bytes octal escaped, eg.
+// "\001\000\032+<M^", or as colon separated hex, eg. 1:0:1a:2b:3c:4d:5e.
+
+#[derive(Serialize, Debug, Default)]
+pub struct ClientId {
+    pub hex: String,
+    pub kind: String,
+    #[serde(rename = "hardware-type", skip_serializing_if = "Option::is_none")]
+    pub hardware_type: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iaid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enterprise: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(rename = "mac-mismatch")]
+    pub mac_mismatch: bool,
+}
+
+fn is_colon_hex(uid: &str) -> bool {
+    let parts: Vec<&str> = uid.split(':').collect();
+    parts.len() > 1
+        && parts
+            .iter()
+            .all(|x| !x.is_empty() && x.len() <= 2 && x.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn unescape(uid: &str) -> Vec<u8> {
+    let bytes = uid.as_bytes();
+    let mut result = Vec::new();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] != b'\\' || index + 1 >= bytes.len() {
+            result.push(bytes[index]);
+            index += 1;
+            continue;
+        }
+        // Taken from the bytes, since the text after a backslash can be
+        // anything in a malformed uid.
+        let window = &bytes[index + 1..(index + 4).min(bytes.len())];
+        let octal = std::str::from_utf8(window)
+            .ok()
+            .filter(|x| x.len() == 3 && x.bytes().all(|c| (b'0'..=b'7').contains(&c)))
+            .and_then(|x| u8::from_str_radix(x, 8).ok());
+        match octal {
+            Some(value) => {
+                result.push(value);
+                index += 4;
+            }
+            None => {
+                result.push(bytes[index + 1]);
+                index += 2;
+            }
+        }
+    }
+    result
+}
+
+pub fn parse_uid(uid: &str) -> Vec<u8> {
+    if is_colon_hex(uid) {
+        uid.split(':')
+            .filter_map(|x| u8::from_str_radix(x, 16).ok())
+            .collect()
+    } else {
+        unescape(uid)
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+fn to_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn to_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn link_layer(client_id: &mut ClientId, hardware_type: u16, address: &[u8]) {
+    client_id.hardware_type = Some(hardware_type);
+    if hardware_type == 1 && address.len() == 6 {
+        client_id.mac = Some(to_hex(address));
+    } else {
+        client_id.identifier = Some(to_hex(address));
+    }
+}
+
+// RFC 8415 section 11, the DUID-LLT time is seconds since 2000-01-01 UTC.
+fn decode_duid(client_id: &mut ClientId, duid: &[u8]) {
+    if duid.len() < 2 {
+        client_id.kind = "duid".to_string();
+        return;
+    }
+    let body = &duid[2..];
+    match to_u16(duid) {
+        1 if body.len() >= 6 => {
+            client_id.kind = "duid-llt".to_string();
+            if let Ok(epoch) = DateTime::parse_from_rfc3339("2000-01-01T00:00:00Z") {
+                let time = epoch + chrono::Duration::seconds(to_u32(&body[2..6]) as i64);
+                client_id.time = Some(time.with_timezone(&Utc).to_rfc3339());
+            }
+            link_layer(client_id, to_u16(body), &body[6..]);
+        }
+        2 if body.len() >= 4 => {
+            client_id.kind = "duid-en".to_string();
+            client_id.enterprise = Some(to_u32(body));
+            client_id.identifier = Some(to_hex(&body[4..]));
+        }
+        3 if body.len() >= 2 => {
+            client_id.kind = "duid-ll".to_string();
+            link_layer(client_id, to_u16(body), &body[2..]);
+        }
+        4 if body.len() == 16 => {
+            client_id.kind = "duid-uuid".to_string();
+            let hex: String = body.iter().map(|x| format!("{:02x}", x)).collect();
+            client_id.uuid = Some(format!(
+                "{}-{}-{}-{}-{}",
+                &hex[0..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..32]
+            ));
+        }
+        _ => client_id.kind = "duid".to_string(),
+    }
+}
+
+// `mac` is the lease hardware-ethernet, used to flag identifiers carrying a
+// different link-layer address.
+pub fn decode(uid: &str, mac: &str) -> ClientId {
+    let bytes = parse_uid(uid);
+    let mut client_id = ClientId {
+        hex: to_hex(&bytes),
+        kind: "unknown".to_string(),
+        ..Default::default()
+    };
+
+    match bytes.split_first() {
+        // RFC 4361, type 255 followed by a 4 byte IAID and a DUID.
+        Some((255, rest)) if rest.len() >= 4 => {
+            client_id.iaid = Some(to_u32(rest));
+            decode_duid(&mut client_id, &rest[4..]);
+        }
+        Some((0, rest)) if !rest.is_empty() => {
+            client_id.kind = "text".to_string();
+            client_id.text = Some(String::from_utf8_lossy(rest).to_string());
+        }
+        Some((hardware_type, address)) if *hardware_type != 255 && !address.is_empty() => {
+            client_id.kind = "hardware".to_string();
+            link_layer(&mut client_id, *hardware_type as u16, address);
+        }
+        _ => {}
+    }
+
+    if let Some(id_mac) = &client_id.mac {
+        client_id.mac_mismatch = !id_mac.eq_ignore_ascii_case(mac);
+    }
+    client_id
+}
+
+impl ClientId {
+    pub fn describe(&self) -> String {
+        let mut parts = vec![self.kind.clone()];
+        if let Some(iaid) = self.iaid {
+            parts.push(format!("iaid {}", iaid));
+        }
+        if let Some(hardware_type) = self.hardware_type.filter(|x| *x != 1) {
+            parts.push(format!("hardware type {}", hardware_type));
+        }
+        if let Some(mac) = &self.mac {
+            parts.push(mac.clone());
+        }
+        if let Some(enterprise) = self.enterprise {
+            parts.push(format!("enterprise {}", enterprise));
+        }
+        if let Some(identifier) = &self.identifier {
+            parts.push(identifier.clone());
+        }
+        if let Some(time) = &self.time {
+            parts.push(format!("generated {}", time));
+        }
+        if let Some(uuid) = &self.uuid {
+            parts.push(uuid.clone());
+        }
+        if let Some(text) = &self.text {
+            parts.push(format!("'{}'", text));
+        }
+        if self.kind == "unknown" {
+            parts.push(self.hex.clone());
+        }
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: &str = "00:1a:2b:3c:4d:5e";
+
+    #[test]
+    fn parses_octal_escaped_uid() {
+        assert_eq!(
+            parse_uid("\\001\\000\\032+<M^"),
+            vec![0x01, 0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]
+        );
+    }
+
+    #[test]
+    fn parses_colon_hex_uid() {
+        assert_eq!(
+            parse_uid("1:0:1a:2b:3c:4d:5e"),
+            vec![0x01, 0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]
+        );
+    }
+
+    #[test]
+    fn malformed_escape_does_not_panic() {
+        assert_eq!(parse_uid("\\é1"), "é1".as_bytes().to_vec());
+        assert_eq!(parse_uid("a\\"), b"a\\".to_vec());
+        assert_eq!(parse_uid("\\+12"), b"+12".to_vec());
+    }
+
+    #[test]
+    fn decodes_type_1_mac() {
+        let client_id = decode("1:0:1a:2b:3c:4d:5e", MAC);
+        assert_eq!(client_id.kind, "hardware");
+        assert_eq!(client_id.hardware_type, Some(1));
+        assert_eq!(client_id.mac.as_deref(), Some(MAC));
+        assert!(!client_id.mac_mismatch);
+    }
+
+    #[test]
+    fn flags_uid_mac_different_from_hardware_ethernet() {
+        let client_id = decode("1:0:1a:2b:3c:4d:5e", "00:1a:2b:3c:4d:5f");
+        assert!(client_id.mac_mismatch);
+    }
+
+    #[test]
+    fn decodes_text_identifier() {
+        let client_id = decode("\\000laptop", MAC);
+        assert_eq!(client_id.kind, "text");
+        assert_eq!(client_id.text.as_deref(), Some("laptop"));
+    }
+
+    #[test]
+    fn decodes_iaid_and_duid_llt() {
+        let client_id = decode("ff:0:0:0:1:0:1:0:1:0:0:0:3c:0:1a:2b:3c:4d:5e", MAC);
+        assert_eq!(client_id.kind, "duid-llt");
+        assert_eq!(client_id.iaid, Some(1));
+        assert_eq!(client_id.time.as_deref(), Some("2000-01-01T00:01:00+00:00"));
+        assert_eq!(client_id.mac.as_deref(), Some(MAC));
+        assert!(!client_id.mac_mismatch);
+    }
+
+    #[test]
+    fn decodes_iaid_and_duid_en() {
+        let client_id = decode("ff:0:0:0:2:0:2:0:0:0:9:ab:cd", MAC);
+        assert_eq!(client_id.kind, "duid-en");
+        assert_eq!(client_id.iaid, Some(2));
+        assert_eq!(client_id.enterprise, Some(9));
+        assert_eq!(client_id.identifier.as_deref(), Some("ab:cd"));
+    }
+
+    #[test]
+    fn decodes_iaid_and_duid_ll() {
+        let client_id = decode("ff:0:0:0:3:0:3:0:1:aa:bb:cc:dd:ee:ff", MAC);
+        assert_eq!(client_id.kind, "duid-ll");
+        assert_eq!(client_id.mac.as_deref(), Some("aa:bb:cc:dd:ee:ff"));
+        assert!(client_id.mac_mismatch);
+    }
+
+    #[test]
+    fn decodes_iaid_and_duid_uuid() {
+        let client_id = decode("ff:0:0:0:4:0:4:0:1:2:3:4:5:6:7:8:9:a:b:c:d:e:f", MAC);
+        assert_eq!(client_id.kind, "duid-uuid");
+        assert_eq!(
+            client_id.uuid.as_deref(),
+            Some("00010203-0405-0607-0809-0a0b0c0d0e0f")
+        );
+    }
+}
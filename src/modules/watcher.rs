@@ -0,0 +1,393 @@
+use super::config;
+use super::error::DhcpctlError;
+use super::leases::{self, Lease};
+use super::reqwest_handler;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::str::FromStr;
+
+const HISTORY_FILE: &str = "lease-history.jsonl";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    NewMac,
+    IpChange,
+    HostnameChange,
+    LeaseExpired,
+    UnknownVendor,
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventKind::NewMac => write!(f, "new-mac"),
+            EventKind::IpChange => write!(f, "ip-change"),
+            EventKind::HostnameChange => write!(f, "hostname-change"),
+            EventKind::LeaseExpired => write!(f, "lease-expired"),
+            EventKind::UnknownVendor => write!(f, "unknown-vendor"),
+        }
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "new-mac" => Ok(EventKind::NewMac),
+            "ip-change" => Ok(EventKind::IpChange),
+            "hostname-change" => Ok(EventKind::HostnameChange),
+            "lease-expired" => Ok(EventKind::LeaseExpired),
+            "unknown-vendor" => Ok(EventKind::UnknownVendor),
+            _ => Err(format!("Unknown event '{}'.", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LeaseEvent {
+    pub event: EventKind,
+    pub timestamp: String,
+    pub mac: String,
+    pub ip: String,
+    pub hostname: Option<String>,
+    pub previous: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LeaseState {
+    pub ip: String,
+    pub hostname: Option<String>,
+    pub ends: String,
+}
+
+// Active leases keyed by MAC address and IP, since a client can hold more
+// than one lease.
+pub type Snapshot = BTreeMap<(String, String), LeaseState>;
+
+pub fn snapshot(leases: &[Lease]) -> Snapshot {
+    leases
+        .iter()
+        .filter(|x| x.binding_state == "active")
+        .map(|x| {
+            (
+                (x.hardware_ethernet.to_lowercase(), x.ip.clone()),
+                LeaseState {
+                    ip: x.ip.clone(),
+                    hostname: x.client_hostname.clone(),
+                    ends: x.ends.clone(),
+                },
+            )
+        })
+        .collect()
+}
+
+fn event(kind: EventKind, mac: &str, state: &LeaseState, previous: Option<String>) -> LeaseEvent {
+    LeaseEvent {
+        event: kind,
+        timestamp: Utc::now().to_rfc3339(),
+        mac: mac.to_string(),
+        ip: state.ip.clone(),
+        hostname: state.hostname.clone(),
+        previous,
+    }
+}
+
+// Per MAC, a lease on a new IP is paired with one that went away as an
+// ip-change. A further lease for a known MAC is an ip-change without a
+// previous IP.
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<LeaseEvent> {
+    let mut events = Vec::new();
+    let macs: BTreeSet<&String> = old.keys().chain(new.keys()).map(|(mac, _)| mac).collect();
+
+    for mac in macs {
+        let leases = |snapshot: &'_ Snapshot| -> Vec<LeaseState> {
+            snapshot
+                .iter()
+                .filter(|((x, _), _)| x == mac)
+                .map(|(_, state)| state.clone())
+                .collect()
+        };
+        let (before, after) = (leases(old), leases(new));
+
+        if before.is_empty() {
+            events.push(event(EventKind::NewMac, mac, &after[0], None));
+            continue;
+        }
+
+        let mut removed = before
+            .iter()
+            .filter(|x| !after.iter().any(|y| y.ip == x.ip));
+        for state in after.iter() {
+            match before.iter().find(|x| x.ip == state.ip) {
+                Some(previous) => {
+                    if previous.hostname != state.hostname {
+                        events.push(event(
+                            EventKind::HostnameChange,
+                            mac,
+                            state,
+                            previous.hostname.clone(),
+                        ));
+                    }
+                }
+                None => events.push(event(
+                    EventKind::IpChange,
+                    mac,
+                    state,
+                    removed.next().map(|x| x.ip.clone()),
+                )),
+            }
+        }
+        for state in removed {
+            events.push(event(EventKind::LeaseExpired, mac, state, None));
+        }
+    }
+    events
+}
+
+// One snapshot per profile, so watching a second server doesn't diff
+// against the first one.
+fn snapshot_file(name: &str) -> String {
+    let profile: String = config::active_profile_name()
+        .chars()
+        .map(|x| {
+            if x.is_ascii_alphanumeric() || x == '-' || x == '_' {
+                x
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("lease-snapshot-{}-{}.json", profile, name)
+}
+
+// Stored as a list of MAC and lease pairs, since JSON keys are strings. A
+// snapshot in an older format is ignored and seeded again.
+pub fn load_snapshot(name: &str) -> Result<Option<Snapshot>, DhcpctlError> {
+    let file = config::data_file(&snapshot_file(name))?;
+    if !file.exists() {
+        return Ok(None);
+    }
+    let stored: Option<Vec<(String, LeaseState)>> =
+        serde_json::from_str(&fs::read_to_string(file)?).ok();
+    Ok(stored.map(|x| {
+        x.into_iter()
+            .map(|(mac, state)| ((mac, state.ip.clone()), state))
+            .collect()
+    }))
+}
+
+pub fn store_snapshot(name: &str, snapshot: &Snapshot) -> Result<(), DhcpctlError> {
+    let file = config::data_file(&snapshot_file(name))?;
+    let stored: Vec<(&String, &LeaseState)> = snapshot
+        .iter()
+        .map(|((mac, _), state)| (mac, state))
+        .collect();
+    fs::write(file, serde_json::to_string(&stored)?)?;
+    Ok(())
+}
+
+pub fn append_history(events: &[LeaseEvent]) -> Result<(), DhcpctlError> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(config::data_file(HISTORY_FILE)?)?;
+    for event in events.iter() {
+        writeln!(file, "{}", serde_json::to_string(event)?)?;
+    }
+    Ok(())
+}
+
+pub fn load_history() -> Result<Vec<LeaseEvent>, DhcpctlError> {
+    let file = config::data_file(HISTORY_FILE)?;
+    if !file.exists() {
+        return Ok(Vec::new());
+    }
+    let mut events = Vec::new();
+    for line in BufReader::new(fs::File::open(file)?).lines() {
+        if let Ok(event) = serde_json::from_str::<LeaseEvent>(&line?) {
+            events.push(event);
+        }
+    }
+    Ok(events)
+}
+
+// Each consumer keeps its own snapshot, so the webhook daemon and the MQTT
+// publisher can run side by side without stealing each other's events.
+pub struct Watcher {
+    name: String,
+    previous: Option<Snapshot>,
+    vendor_lookup: bool,
+    pub leases: Vec<Lease>,
+}
+
+impl Watcher {
+    pub fn new(name: &str, vendor_lookup: bool) -> Result<Self, DhcpctlError> {
+        Ok(Watcher {
+            name: name.to_string(),
+            previous: load_snapshot(name)?,
+            vendor_lookup,
+            leases: Vec::new(),
+        })
+    }
+
+    // The first poll without a stored snapshot only seeds the state, otherwise
+    // every lease on the server would be reported as a new MAC.
+    pub async fn poll(&mut self) -> Result<Vec<LeaseEvent>, DhcpctlError> {
+        self.leases = reqwest_handler::run("/leases/").await?;
+        let current = snapshot(&self.leases);
+
+        let mut events = match &self.previous {
+            Some(previous) => diff(previous, &current),
+            None => Vec::new(),
+        };
+
+        if self.vendor_lookup {
+            let unknown = self.unknown_vendors(&events).await;
+            events.extend(unknown);
+        }
+
+        store_snapshot(&self.name, &current)?;
+        self.previous = Some(current);
+        Ok(events)
+    }
+
+    async fn unknown_vendors(&self, events: &[LeaseEvent]) -> Vec<LeaseEvent> {
+        let new_macs: Vec<&LeaseEvent> = events
+            .iter()
+            .filter(|x| x.event == EventKind::NewMac && x.mac.len() >= 8)
+            .collect();
+        if new_macs.is_empty() {
+            return Vec::new();
+        }
+
+        let prefixes: HashSet<&str> = new_macs.iter().map(|x| &x.mac[0..8]).collect();
+        let vendors = match leases::get_vendors(&prefixes).await {
+            Ok(vendors) => vendors,
+            Err(error) => {
+                eprintln!("Vendor lookup failed: {}", error);
+                return Vec::new();
+            }
+        };
+
+        new_macs
+            .into_iter()
+            .filter(|x| !vendors.contains_key(&x.mac[0..8]))
+            .map(|x| LeaseEvent {
+                event: EventKind::UnknownVendor,
+                ..x.clone()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(ip: &str, hostname: Option<&str>) -> LeaseState {
+        LeaseState {
+            ip: ip.to_string(),
+            hostname: hostname.map(|x| x.to_string()),
+            ends: String::new(),
+        }
+    }
+
+    fn snapshot_of(leases: &[(&str, &str, Option<&str>)]) -> Snapshot {
+        leases
+            .iter()
+            .map(|(mac, ip, hostname)| ((mac.to_string(), ip.to_string()), state(ip, *hostname)))
+            .collect()
+    }
+
+    fn kinds(events: &[LeaseEvent]) -> Vec<(EventKind, &str, &str, Option<&str>)> {
+        events
+            .iter()
+            .map(|x| {
+                (
+                    x.event,
+                    x.mac.as_str(),
+                    x.ip.as_str(),
+                    x.previous.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_keeps_every_active_lease_of_a_mac() {
+        let leases: Vec<Lease> = serde_json::from_value(serde_json::json!([
+            {"hardware-ethernet": "AA:BB:CC:00:00:01", "ip": "10.0.1.10", "binding-state": "active"},
+            {"hardware-ethernet": "aa:bb:cc:00:00:01", "ip": "10.0.2.10", "binding-state": "active"},
+            {"hardware-ethernet": "aa:bb:cc:00:00:01", "ip": "10.0.1.11", "binding-state": "free"}
+        ]))
+        .unwrap();
+        let keys: Vec<(String, String)> = snapshot(&leases).into_keys().collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("aa:bb:cc:00:00:01".to_string(), "10.0.1.10".to_string()),
+                ("aa:bb:cc:00:00:01".to_string(), "10.0.2.10".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn new_mac_and_expired_lease() {
+        let old = snapshot_of(&[("aa:00", "10.0.1.10", None)]);
+        let new = snapshot_of(&[("bb:00", "10.0.1.11", None)]);
+        assert_eq!(
+            kinds(&diff(&old, &new)),
+            vec![
+                (EventKind::LeaseExpired, "aa:00", "10.0.1.10", None),
+                (EventKind::NewMac, "bb:00", "10.0.1.11", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn ip_and_hostname_changes() {
+        let old = snapshot_of(&[
+            ("aa:00", "10.0.1.10", None),
+            ("bb:00", "10.0.1.20", Some("old")),
+        ]);
+        let new = snapshot_of(&[
+            ("aa:00", "10.0.1.12", None),
+            ("bb:00", "10.0.1.20", Some("new")),
+        ]);
+        let events = diff(&old, &new);
+        assert_eq!(
+            kinds(&events),
+            vec![
+                (EventKind::IpChange, "aa:00", "10.0.1.12", Some("10.0.1.10")),
+                (EventKind::HostnameChange, "bb:00", "10.0.1.20", Some("old")),
+            ]
+        );
+        assert_eq!(events[1].hostname.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn mac_with_several_leases_does_not_flap() {
+        let leases = snapshot_of(&[("aa:00", "10.0.1.10", None), ("aa:00", "10.0.2.10", None)]);
+        assert!(diff(&leases, &leases).is_empty());
+    }
+
+    #[test]
+    fn mac_gaining_and_losing_a_second_lease() {
+        let one = snapshot_of(&[("aa:00", "10.0.1.10", None)]);
+        let two = snapshot_of(&[("aa:00", "10.0.1.10", None), ("aa:00", "10.0.2.10", None)]);
+        assert_eq!(
+            kinds(&diff(&one, &two)),
+            vec![(EventKind::IpChange, "aa:00", "10.0.2.10", None)]
+        );
+        assert_eq!(
+            kinds(&diff(&two, &one)),
+            vec![(EventKind::LeaseExpired, "aa:00", "10.0.2.10", None)]
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use super::config;
+use super::config::ConfyConfig;
+use super::error::DhcpctlError;
+use super::watcher::{self, EventKind, LeaseEvent, Watcher};
+use chrono::prelude::*;
+use colored::Colorize;
+use prettytable::{cell, format, row, Row, Table};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::Duration;
+
+const DEAD_LETTER_FILE: &str = "webhook-dead-letter.jsonl";
+const DEFAULT_MESSAGE: &str = "[{event}] {mac} {ip} {hostname} {previous}";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookTemplate {
+    #[default]
+    Generic,
+    Slack,
+    Teams,
+}
+
+impl fmt::Display for WebhookTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebhookTemplate::Generic => write!(f, "generic"),
+            WebhookTemplate::Slack => write!(f, "slack"),
+            WebhookTemplate::Teams => write!(f, "teams"),
+        }
+    }
+}
+
+impl FromStr for WebhookTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "generic" => Ok(WebhookTemplate::Generic),
+            "slack" => Ok(WebhookTemplate::Slack),
+            "teams" => Ok(WebhookTemplate::Teams),
+            _ => Err(format!("Unknown template '{}'.", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub template: WebhookTemplate,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+}
+
+impl Webhook {
+    fn wants(&self, event: &LeaseEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event.event)
+    }
+
+    fn payload(&self, event: &LeaseEvent) -> Value {
+        let message = render_message(self.message.as_deref().unwrap_or(DEFAULT_MESSAGE), event);
+        match self.template {
+            WebhookTemplate::Generic => {
+                let mut payload = json!(event);
+                payload["message"] = json!(message);
+                payload
+            }
+            WebhookTemplate::Slack => json!({ "text": message }),
+            WebhookTemplate::Teams => json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "summary": format!("dhcpctl {}", event.event),
+                "text": message,
+            }),
+        }
+    }
+}
+
+// Placeholders: {event}, {timestamp}, {mac}, {ip}, {hostname} and {previous}.
+fn render_message(template: &str, event: &LeaseEvent) -> String {
+    template
+        .replace("{event}", &event.event.to_string())
+        .replace("{timestamp}", &event.timestamp)
+        .replace("{mac}", &event.mac)
+        .replace("{ip}", &event.ip)
+        .replace("{hostname}", &event.hostname.clone().unwrap_or_default())
+        .replace("{previous}", &event.previous.clone().unwrap_or_default())
+        .trim()
+        .to_string()
+}
+
+fn table_format() -> format::TableFormat {
+    format::FormatBuilder::new()
+        .column_separator(' ')
+        .separator(
+            format::LinePosition::Title,
+            format::LineSeparator::new('-', ' ', ' ', ' '),
+        )
+        .padding(1, 1)
+        .build()
+}
+
+pub fn add_webhook(
+    url: &str,
+    template: WebhookTemplate,
+    message: Option<String>,
+    events: Vec<EventKind>,
+) -> Result<(), DhcpctlError> {
+    config::check_if_conf_exists()?;
+    let mut conf: ConfyConfig = config::load_config()?;
+    conf.webhooks.retain(|x| x.url != url);
+    conf.webhooks.push(Webhook {
+        url: url.to_owned(),
+        template,
+        message,
+        events,
+    });
+    config::store_config(&conf)?;
+    println!("Webhook => {}", url.green());
+    Ok(())
+}
+
+pub fn remove_webhook(url: &str) -> Result<(), DhcpctlError> {
+    let mut conf: ConfyConfig = config::load_config()?;
+    let count = conf.webhooks.len();
+    conf.webhooks.retain(|x| x.url != url);
+    if conf.webhooks.len() == count {
+        println!("No webhook found by that URL");
+        return Ok(());
+    }
+    config::store_config(&conf)?;
+    println!("Removed webhook {}", url.green());
+    Ok(())
+}
+
+pub fn list_webhooks() -> Result<(), DhcpctlError> {
+    let conf: ConfyConfig = config::load_config()?;
+    if conf.webhooks.is_empty() {
+        println!("No webhooks configured");
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_format(table_format());
+    table.set_titles(row!(b -> "URL", b -> "Template", b -> "Events", b -> "Message"));
+    for webhook in conf.webhooks.iter() {
+        let events: Vec<String> = webhook.events.iter().map(|x| x.to_string()).collect();
+        let row: Row = row!(
+            &webhook.url,
+            webhook.template,
+            if events.is_empty() {
+                "all".to_string()
+            } else {
+                events.join(",")
+            },
+            webhook.message.as_deref().unwrap_or(DEFAULT_MESSAGE),
+        );
+        table.add_row(row);
+    }
+    table.printstd();
+    Ok(())
+}
+
+fn client() -> Result<reqwest::Client, DhcpctlError> {
+    Ok(reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?)
+}
+
+// Retries back off exponentially, starting at one second.
+async fn deliver(
+    client: &reqwest::Client,
+    url: &str,
+    payload: &Value,
+    retries: u32,
+) -> Result<(), String> {
+    let mut attempt = 0;
+    loop {
+        let error = match client.post(url).json(payload).send().await {
+            Ok(response) => match response.error_for_status() {
+                Ok(_) => return Ok(()),
+                Err(error) => error.to_string(),
+            },
+            Err(error) => error.to_string(),
+        };
+
+        if attempt >= retries {
+            return Err(error);
+        }
+        tokio::time::sleep(Duration::from_secs(1 << attempt.min(6))).await;
+        attempt += 1;
+    }
+}
+
+fn dead_letter(url: &str, payload: &Value, error: &str) -> Result<(), DhcpctlError> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(config::data_file(DEAD_LETTER_FILE)?)?;
+    let entry = json!({
+        "timestamp": Utc::now().to_rfc3339(),
+        "url": url,
+        "error": error,
+        "payload": payload,
+    });
+    writeln!(file, "{}", entry)?;
+    Ok(())
+}
+
+async fn dispatch(
+    client: &reqwest::Client,
+    webhooks: &[Webhook],
+    events: &[LeaseEvent],
+    retries: u32,
+) -> Result<(), DhcpctlError> {
+    for event in events.iter() {
+        for webhook in webhooks.iter().filter(|x| x.wants(event)) {
+            let payload = webhook.payload(event);
+            if let Err(error) = deliver(client, &webhook.url, &payload, retries).await {
+                eprintln!(
+                    "{} {}: {}",
+                    "Delivery failed, moved to dead-letter file".red(),
+                    webhook.url,
+                    error
+                );
+                dead_letter(&webhook.url, &payload, &error)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn test_webhook(url: Option<String>) -> Result<(), DhcpctlError> {
+    let conf: ConfyConfig = config::load_config()?;
+    let webhooks: Vec<Webhook> = match url {
+        Some(url) => match conf.webhooks.iter().find(|x| x.url == url) {
+            Some(webhook) => vec![webhook.clone()],
+            None => vec![Webhook {
+                url,
+                template: WebhookTemplate::Generic,
+                message: None,
+                events: Vec::new(),
+            }],
+        },
+        None => conf.webhooks,
+    };
+
+    if webhooks.is_empty() {
+        println!("No webhooks configured");
+        return Ok(());
+    }
+
+    let event = LeaseEvent {
+        event: EventKind::NewMac,
+        timestamp: Utc::now().to_rfc3339(),
+        mac: "00:00:5e:00:53:01".to_string(),
+        ip: "192.0.2.10".to_string(),
+        hostname: Some("dhcpctl-test".to_string()),
+        previous: None,
+    };
+
+    let client = client()?;
+    for webhook in webhooks.iter() {
+        match deliver(&client, &webhook.url, &webhook.payload(&event), 0).await {
+            Ok(_) => println!("{} {}", "OK".green(), webhook.url),
+            Err(error) => println!("{} {}: {}", "FAILED".red(), webhook.url, error),
+        }
+    }
+    Ok(())
+}
+
+pub async fn run_daemon(
+    interval: u64,
+    retries: u32,
+    vendor_lookup: bool,
+    once: bool,
+) -> Result<(), DhcpctlError> {
+    let conf: ConfyConfig = config::load_config()?;
+    let client = client()?;
+    let mut watcher = Watcher::new("daemon", vendor_lookup)?;
+
+    loop {
+        match watcher.poll().await {
+            Ok(events) => {
+                for event in events.iter() {
+                    println!(
+                        "{} {} {} {}",
+                        event.timestamp,
+                        event.event.to_string().bold(),
+                        event.mac,
+                        event.ip
+                    );
+                }
+                watcher::append_history(&events)?;
+                dispatch(&client, &conf.webhooks, &events, retries).await?;
+            }
+            Err(error) => eprintln!("{} {}", "Polling leases failed:".red(), error),
+        }
+
+        if once {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    fn lease_event() -> LeaseEvent {
+        LeaseEvent {
+            event: EventKind::IpChange,
+            timestamp: "2021-09-01T10:00:00+00:00".to_string(),
+            mac: "aa:bb:cc:00:00:01".to_string(),
+            ip: "10.0.1.12".to_string(),
+            hostname: Some("laptop".to_string()),
+            previous: Some("10.0.1.10".to_string()),
+        }
+    }
+
+    fn webhook(template: WebhookTemplate, message: Option<&str>) -> Webhook {
+        Webhook {
+            url: String::new(),
+            template,
+            message: message.map(|x| x.to_string()),
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn renders_message_placeholders() {
+        assert_eq!(
+            render_message(DEFAULT_MESSAGE, &lease_event()),
+            "[ip-change] aa:bb:cc:00:00:01 10.0.1.12 laptop 10.0.1.10"
+        );
+        let mut event = lease_event();
+        event.hostname = None;
+        event.previous = None;
+        assert_eq!(
+            render_message("{timestamp} {mac} {hostname} {previous}", &event),
+            "2021-09-01T10:00:00+00:00 aa:bb:cc:00:00:01"
+        );
+    }
+
+    #[test]
+    fn generic_payload_has_the_event_fields_and_message() {
+        let payload =
+            webhook(WebhookTemplate::Generic, Some("{event} {ip}")).payload(&lease_event());
+        assert_eq!(payload["event"], "ip-change");
+        assert_eq!(payload["mac"], "aa:bb:cc:00:00:01");
+        assert_eq!(payload["previous"], "10.0.1.10");
+        assert_eq!(payload["message"], "ip-change 10.0.1.12");
+    }
+
+    #[test]
+    fn slack_and_teams_payloads() {
+        let slack = webhook(WebhookTemplate::Slack, Some("{mac}")).payload(&lease_event());
+        assert_eq!(slack, json!({ "text": "aa:bb:cc:00:00:01" }));
+
+        let teams = webhook(WebhookTemplate::Teams, Some("{mac}")).payload(&lease_event());
+        assert_eq!(teams["@type"], "MessageCard");
+        assert_eq!(teams["summary"], "dhcpctl ip-change");
+        assert_eq!(teams["text"], "aa:bb:cc:00:00:01");
+    }
+
+    #[test]
+    fn event_filter() {
+        let mut hook = webhook(WebhookTemplate::Generic, None);
+        assert!(hook.wants(&lease_event()));
+        hook.events = vec![EventKind::NewMac];
+        assert!(!hook.wants(&lease_event()));
+    }
+
+    // A one-shot HTTP stand-in that answers with `status` and hands back the
+    // request body.
+    fn stand_in(status: &'static str) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+            String::from_utf8(body).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn delivers_the_payload() {
+        let (url, handle) = stand_in("200 OK");
+        let payload = webhook(WebhookTemplate::Slack, None).payload(&lease_event());
+        deliver(&client().unwrap(), &url, &payload, 0)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_str(&handle.join().unwrap()).unwrap();
+        assert_eq!(body, payload);
+    }
+
+    #[tokio::test]
+    async fn reports_a_failed_delivery() {
+        let (url, handle) = stand_in("500 Internal Server Error");
+        let payload = json!({ "text": "x" });
+        assert!(deliver(&client().unwrap(), &url, &payload, 0)
+            .await
+            .is_err());
+        handle.join().unwrap();
+    }
+}